@@ -116,7 +116,11 @@ enum EnumTestChoices {
 
 #[derive(Reflect, Default, QevyEntity)]
 #[reflect(QevyEntity, Default)]
-#[qevy_entity(entity_type = "Solid")]
+#[qevy_entity(
+    entity_type = "Solid",
+    inputs = [("Open", "Opens the door"), ("SetSpeed(float)", "Sets how fast the door moves")],
+    outputs = [("OnOpen", "Fired once the door is open")]
+)]
 struct TestSolidClass;
 
 #[derive(Reflect, Default, QevyEntity)]
@@ -1,5 +1,3 @@
-use std::time::Duration;
-
 #[cfg(feature = "avian")]
 use avian3d::prelude::*;
 
@@ -41,23 +39,7 @@ fn main() {
             // RapierDebugRenderPlugin::default()
         ))
         .add_systems(Startup, (spawn_map, spawn_character))
-        .add_systems(
-            Update,
-            (
-                movement,
-                grab_mouse,
-                my_post_build_map_system,
-                door_system,
-                qevy::build::post_build_map_system,
-                // Avian
-                #[cfg(feature = "avian")]
-                qevy::gameplay_systems::avian_trigger_system,
-                // Rapier
-                #[cfg(feature = "rapier")]
-                #[cfg(not(feature = "avian"))]
-                qevy::gameplay_systems::rapier_trigger_system,
-            ),
-        )
+        .add_systems(Update, (movement, grab_mouse, my_post_build_map_system))
         .run();
 }
 
@@ -65,7 +47,6 @@ fn spawn_map(mut commands: Commands, asset_server: Res<AssetServer>) {
     // spawn the map
     commands.spawn(qevy::components::Map {
         asset: asset_server.load("example.map"), // map must be under `assets` folder
-        ..default()
     });
 }
 
@@ -113,7 +94,7 @@ fn spawn_character(mut commands: Commands, mut q_windows: Query<&mut Window, Wit
             ..default()
         },
         Projection::Perspective(PerspectiveProjection {
-            fov: 1.5708,
+            fov: std::f32::consts::FRAC_PI_2,
             ..default()
         }),
         Transform::from_xyz(0.0, 0.0, 0.0).looking_at(Vec3::ZERO, Vec3::Y),
@@ -210,74 +191,6 @@ fn movement(
     }
 }
 
-pub fn door_system(
-    time: Res<Time>,
-    mut ev_reader: EventReader<TriggeredEvent>,
-    mut q_doors: Query<(&mut Door, &TriggerTarget, &mut Transform, &mut Mover)>,
-) {
-    for triggered_event in ev_reader.read() {
-        for (_, trigger_target, _, mut mover) in q_doors.iter_mut() {
-            if trigger_target.target_name == triggered_event.target {
-                match mover.state {
-                    MoverState::AtStart => {
-                        mover.state = MoverState::MovingToDestination(Timer::new(
-                            mover.moving_time,
-                            TimerMode::Once,
-                        ))
-                    }
-                    MoverState::AtDestination(_) => {
-                        mover.state = MoverState::AtDestination(Timer::new(
-                            mover.destination_time,
-                            TimerMode::Once,
-                        ))
-                    }
-                    _ => {}
-                }
-            }
-        }
-    }
-
-    let delta = time.delta();
-    for (door, _, mut transform, mut mover) in q_doors.iter_mut() {
-        let destination_offset = mover.destination_offset;
-        let moving_time_secs = mover.moving_time.as_secs_f32();
-        match &mut mover.state {
-            MoverState::AtStart => {}
-            MoverState::MovingToDestination(ref mut timer) => {
-                timer.tick(delta);
-                transform.translation +=
-                    destination_offset / moving_time_secs * delta.as_secs_f32();
-                if timer.just_finished() {
-                    if door.open_once {
-                        mover.state =
-                            MoverState::AtDestination(Timer::new(Duration::MAX, TimerMode::Once))
-                    } else {
-                        mover.state = MoverState::AtDestination(Timer::new(
-                            mover.destination_time,
-                            TimerMode::Once,
-                        ))
-                    }
-                }
-            }
-            MoverState::AtDestination(ref mut timer) => {
-                timer.tick(delta);
-                if timer.just_finished() {
-                    mover.state =
-                        MoverState::MovingToStart(Timer::new(mover.moving_time, TimerMode::Once))
-                }
-            }
-            MoverState::MovingToStart(ref mut timer) => {
-                timer.tick(delta);
-                transform.translation -=
-                    destination_offset / moving_time_secs * delta.as_secs_f32();
-                if timer.just_finished() {
-                    mover.state = MoverState::AtStart;
-                }
-            }
-        }
-    }
-}
-
 fn grab_mouse(
     mut windows: Query<&mut Window>,
     mouse: Res<ButtonInput<MouseButton>>,
@@ -1,6 +1,9 @@
 use qevy_types::QevyEntityType;
 use syn::{Attribute, DeriveInput, Meta, MetaNameValue};
 
+// (path, frame, skin, scale)
+type ModelAttribute = (String, Option<u32>, Option<u32>, Option<u32>);
+
 #[derive(deluxe::ExtractAttributes)]
 #[deluxe(attributes(qevy_entity))]
 struct QevyEntityStructAttributes {
@@ -8,15 +11,19 @@ struct QevyEntityStructAttributes {
     entity_type: String,
     #[deluxe(default = None)]
     entity_name: Option<String>,
-    // (path, frame, skin, scale)
     #[deluxe(default = None)]
-    model: Option<(String, Option<u32>, Option<u32>, Option<u32>)>,
+    model: Option<ModelAttribute>,
     // -x,-y,-z,+x,+y,+z
     #[deluxe(default = None)]
     size: Option<(u32, u32, u32, u32, u32, u32)>,
     // (r, g, b), 0-255
     #[deluxe(default = None)]
     color: Option<(u32, u32, u32)>,
+    // [(name, description)], the name can give the parameter type: "SetSpeed(float)"
+    #[deluxe(default)]
+    inputs: Vec<(String, String)>,
+    #[deluxe(default)]
+    outputs: Vec<(String, String)>,
 }
 
 #[derive(deluxe::ExtractAttributes)]
@@ -26,9 +33,12 @@ struct QevyEntityFieldAttributes {
     base_class: Option<String>,
 }
 
+// (comments, base class)
+type FieldAttributes = (String, Option<String>);
+
 fn extract_qevy_entity_field_comments(
     ast: &mut DeriveInput,
-) -> deluxe::Result<(Vec<String>, Vec<(String, Option<String>)>)> {
+) -> deluxe::Result<(Vec<String>, Vec<FieldAttributes>)> {
     let mut field_names = Vec::new();
     let mut field_attributes = Vec::new();
 
@@ -61,6 +71,8 @@ pub(crate) fn qevy_entity_derive_macro2(
         model,
         size,
         color,
+        inputs,
+        outputs,
     } = deluxe::extract_attributes(&mut ast)?;
 
     let model_string = model
@@ -73,7 +85,7 @@ pub(crate) fn qevy_entity_derive_macro2(
                 scale.unwrap_or(32)
             )
         })
-        .unwrap_or_else(|| String::new());
+        .unwrap_or_default();
 
     let entity_size_string = size
         .map(|(min_x, min_y, min_z, max_x, max_y, max_z)| {
@@ -82,14 +94,29 @@ pub(crate) fn qevy_entity_derive_macro2(
                 min_x, min_y, min_z, max_x, max_y, max_z
             )
         })
-        .unwrap_or_else(|| String::new());
+        .unwrap_or_default();
 
     let color_string = color
         .map(|(r, g, b)| format!("color({} {} {})", r, g, b))
-        .unwrap_or_else(|| String::new());
+        .unwrap_or_default();
+
+    let io_string = inputs
+        .iter()
+        .map(|io| ("input", io))
+        .chain(outputs.iter().map(|io| ("output", io)))
+        .map(|(kind, (name, description))| {
+            let name = if name.contains('(') {
+                name.clone()
+            } else {
+                format!("{}(void)", name)
+            };
+            format!("\t{} {} : \"{}\"", kind, name, description)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
 
     let entity_type = QevyEntityType::from_short_string(entity_type.as_str())
-        .expect(format!("Invalid entity type: {}", entity_type).as_str());
+        .unwrap_or_else(|| panic!("Invalid entity type: {}", entity_type));
 
     let entity_type = entity_type.to_fgd_string();
 
@@ -129,6 +156,10 @@ pub(crate) fn qevy_entity_derive_macro2(
     // Generate code
     let generated_code = quote::quote!(
         impl #impl_generics QevyEntity for #ident #type_generics #where_clause {
+            fn get_classname(&self) -> &'static str {
+                #struct_name
+            }
+
             fn get_export_string(
                 &self,
                 my_registration: &bevy::reflect::TypeRegistration,
@@ -156,7 +187,9 @@ pub(crate) fn qevy_entity_derive_macro2(
                 let description = #entity_description;
                 let entity_type = #entity_type;
 
-                let types_string = match type_info {
+                let io_string = #io_string;
+
+                let mut types_string = match type_info {
                     bevy::reflect::TypeInfo::Struct(info) => {
                         let mut types_string = String::new();
 
@@ -193,6 +226,13 @@ pub(crate) fn qevy_entity_derive_macro2(
                     _ => todo!(),
                 };
 
+                if !io_string.is_empty() {
+                    if !types_string.is_empty() {
+                        types_string.push('\n');
+                    }
+                    types_string.push_str(io_string);
+                }
+
                 format!(
                     "{} {} {} {} {} = {} : \"{}\" [\n{}\n]\n",
                     entity_type, base_class_string, size_string, color_string, model_string, short_name, description, types_string
@@ -209,13 +249,10 @@ fn get_comments(attrs: &[Attribute]) -> String {
     for attr in attrs {
         if let Meta::NameValue(MetaNameValue { path, value, .. }) = &attr.meta {
             if path.is_ident("doc") {
-                match value {
-                    syn::Expr::Lit(lit) => {
-                        if let syn::Lit::Str(lit_str) = &lit.lit {
-                            docs.push(lit_str.value());
-                        }
+                if let syn::Expr::Lit(lit) = value {
+                    if let syn::Lit::Str(lit_str) = &lit.lit {
+                        docs.push(lit_str.value());
                     }
-                    _ => {}
                 }
             }
         }
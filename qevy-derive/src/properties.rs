@@ -111,6 +111,41 @@ pub(crate) fn qevy_property_derive_macro2(
         _ => panic!("Unsupported property type: {}", property_type),
     };
 
+    let variant_idents = field_names
+        .iter()
+        .map(|name| syn::Ident::new(name, proc_macro2::Span::call_site()))
+        .collect::<Vec<_>>();
+    // flags are a bitmask of the variants, but an enum only holds one of them,
+    // so anything but a single flag is rejected rather than losing the others
+    // choices are the key of the variant, the index unless it's overridden
+    let set_from_map_value_body = match property_type.as_str() {
+        "flags" => {
+            let bits = (0..field_names.len()).map(|i| 1_u32 << i);
+            quote::quote!(
+                match value.trim().parse::<u32>() {
+                    #(Ok(#bits) => *self = Self::#variant_idents,)*
+                    _ => return false,
+                }
+                true
+            )
+        }
+        _ => {
+            let keys = field_attrs.iter().enumerate().map(|(index, attributes)| {
+                match attributes.key_override.as_ref() {
+                    Some(key) => key.clone(),
+                    None => index.to_string(),
+                }
+            });
+            quote::quote!(
+                match value.trim() {
+                    #(#keys => *self = Self::#variant_idents,)*
+                    _ => return false,
+                }
+                true
+            )
+        }
+    };
+
     // define impl variables
     let ident = &ast.ident;
     let (impl_generics, type_generics, where_clause) = ast.generics.split_for_impl();
@@ -131,6 +166,10 @@ pub(crate) fn qevy_property_derive_macro2(
 
                 Box::leak(string.into_boxed_str())
             }
+
+            fn set_from_map_value(&mut self, value: &str) -> bool {
+                #set_from_map_value_body
+            }
         }
     );
 
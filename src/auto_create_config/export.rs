@@ -27,7 +27,7 @@ pub(crate) fn create_config(world: &mut World) {
         .collect();
 
     for qevy_entity_reg in qevy_entities_registrations {
-        let config_string = type_reg_to_export_string(qevy_entity_reg, &*types);
+        let config_string = type_reg_to_export_string(qevy_entity_reg, &types);
 
         writer
             .write_all(config_string.as_bytes())
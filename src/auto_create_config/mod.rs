@@ -5,6 +5,7 @@ use bevy::prelude::*;
 use self::{export::create_config, register_types::properties::QevyPropertyPlugin};
 mod export;
 pub mod register_types;
+pub(crate) mod spawn;
 
 #[derive(Resource, Clone, Default)]
 pub(crate) struct QevyRegistry {
@@ -25,18 +26,11 @@ impl Default for AutoCreateConfigSettings {
     }
 }
 
+#[derive(Default)]
 pub struct AutoCreateConfigPlugin {
     pub(crate) settings: AutoCreateConfigSettings,
 }
 
-impl Default for AutoCreateConfigPlugin {
-    fn default() -> Self {
-        AutoCreateConfigPlugin {
-            settings: AutoCreateConfigSettings::default(),
-        }
-    }
-}
-
 impl AutoCreateConfigPlugin {
     pub fn new(save_path: PathBuf) -> Self {
         AutoCreateConfigPlugin {
@@ -51,8 +45,11 @@ impl Plugin for AutoCreateConfigPlugin {
             .init_resource::<QevyRegistry>()
             .register_asset_root()
             .insert_resource(self.settings.clone())
-            .add_systems(Startup, create_config)
-            .add_plugins(QevyPropertyPlugin);
+            .add_systems(Startup, create_config);
+
+        if !app.is_plugin_added::<QevyPropertyPlugin>() {
+            app.add_plugins(QevyPropertyPlugin);
+        }
     }
 }
 
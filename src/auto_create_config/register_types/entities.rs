@@ -9,6 +9,9 @@ use crate::auto_create_config::QevyRegistry;
 
 #[reflect_trait]
 pub trait QevyEntity: Reflect {
+    /// The classname used for the entity in maps and the fgd
+    fn get_classname(&self) -> &'static str;
+
     fn get_export_string(
         &self,
         my_registration: &TypeRegistration,
@@ -30,7 +33,9 @@ impl QevyRegisterSolidClass for App {
         let registry = self.world_mut().resource_mut::<AppTypeRegistry>();
         registry.write().register::<T>();
 
-        let mut registry = self.world_mut().resource_mut::<QevyRegistry>();
+        let mut registry = self
+            .world_mut()
+            .get_resource_or_insert_with(QevyRegistry::default);
         registry.qevy_entities.push(TypeId::of::<T>());
 
         self
@@ -8,7 +8,9 @@ pub(crate) struct QevyPropertyPlugin;
 macro_rules! register_qevy_property_types {
     ($app:expr, $($t:ty),*) => {
         $(
-            $app.register_type_data::<$t, ReflectQevyProperty>();
+            // headless apps (e.g. with MinimalPlugins) may not have registered the type itself yet
+            $app.register_type::<$t>()
+                .register_type_data::<$t, ReflectQevyProperty>();
         )*
     };
 }
@@ -27,6 +29,16 @@ impl Plugin for QevyPropertyPlugin {
 #[reflect_trait]
 pub trait QevyProperty: Reflect {
     fn get_fgd_string(&self, field_name: &str, field_description: &str) -> &'static str;
+
+    /// The entity property the field is read from when spawning map entities
+    fn get_map_key<'a>(&self, field_name: &'a str) -> &'a str {
+        field_name
+    }
+
+    /// Sets the value from an entity property of a map, returns false if it couldn't be parsed
+    fn set_from_map_value(&mut self, _value: &str) -> bool {
+        false
+    }
 }
 
 #[derive(Reflect, Default)]
@@ -47,6 +59,18 @@ impl QevyProperty for QevyAngles {
             .into_boxed_str(),
         )
     }
+
+    fn get_map_key<'a>(&self, _field_name: &'a str) -> &'a str {
+        "angles"
+    }
+
+    fn set_from_map_value(&mut self, value: &str) -> bool {
+        let Some([pitch, yaw, roll]) = parse_floats::<3>(value) else {
+            return false;
+        };
+        *self = QevyAngles { pitch, yaw, roll };
+        true
+    }
 }
 
 // Implementation for bool
@@ -62,17 +86,26 @@ impl QevyProperty for bool {
             .into_boxed_str(),
         )
     }
+
+    fn set_from_map_value(&mut self, value: &str) -> bool {
+        match value.trim() {
+            "1" | "true" => *self = true,
+            "0" | "false" => *self = false,
+            _ => return false,
+        }
+        true
+    }
 }
 
 // Implementation for color
 impl QevyProperty for Color {
     fn get_fgd_string(&self, field_name: &str, field_description: &str) -> &'static str {
-        let color = self.to_linear();
+        let color = self.to_srgba();
         let rgb_string = format!(
             "{} {} {}",
-            (color.red * 255.0) as u8,
-            (color.green * 255.0) as u8,
-            (color.blue * 255.0) as u8
+            (color.red * 255.0).round() as u8,
+            (color.green * 255.0).round() as u8,
+            (color.blue * 255.0).round() as u8
         );
 
         Box::leak(
@@ -83,6 +116,31 @@ impl QevyProperty for Color {
             .into_boxed_str(),
         )
     }
+
+    // sRGB between 0 and 255, the same as the fgd default
+    fn set_from_map_value(&mut self, value: &str) -> bool {
+        let Some(rgb) = parse_floats::<3>(value) else {
+            return false;
+        };
+        if rgb
+            .iter()
+            .any(|component| !(0.0..=255.0).contains(component))
+        {
+            return false;
+        }
+        let [red, green, blue] = rgb.map(|component| component / 255.0);
+        *self = Color::srgb(red, green, blue);
+        true
+    }
+}
+
+/// Parses `N` whitespace separated floats, like `origin` or `color` values
+fn parse_floats<const N: usize>(value: &str) -> Option<[f32; N]> {
+    let floats = value
+        .split_whitespace()
+        .map(|component| component.parse::<f32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    floats.try_into().ok()
 }
 
 // Macro to implement QevyProperty for given types
@@ -102,6 +160,18 @@ macro_rules! impl_qevy_property {
                             .into_boxed_str()
                     )
                 }
+
+                fn set_from_map_value(&mut self, value: &str) -> bool {
+                    // strings are kept as they are, numbers may have stray spaces around them
+                    let value = if $label == "string" { value } else { value.trim() };
+                    match value.parse::<$t>() {
+                        Ok(value) => {
+                            *self = value;
+                            true
+                        }
+                        Err(_) => false,
+                    }
+                }
             }
         )*
     };
@@ -113,3 +183,85 @@ impl_qevy_property!(
 );
 impl_qevy_property!("float", true, f64, f32);
 impl_qevy_property!("string", true, String);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Reflect, Default, Debug, PartialEq, QevyProperty)]
+    #[qevy_property(property_type = "choices")]
+    enum Choices {
+        #[default]
+        #[qevy_property(selected_by_default = true)]
+        First,
+        #[qevy_property(key_override = "second")]
+        Second,
+        Third,
+    }
+
+    #[derive(Reflect, Default, Debug, PartialEq, QevyProperty)]
+    #[qevy_property(property_type = "flags")]
+    enum Flags {
+        #[default]
+        First,
+        Second,
+        Third,
+    }
+
+    #[test]
+    fn colors_are_srgb_from_0_to_255() {
+        let mut color = Color::BLACK;
+        assert!(color.set_from_map_value("255 128 0"));
+        assert_eq!(color, Color::srgb(1.0, 128.0 / 255.0, 0.0));
+
+        // a 0-1 color is read on the same scale, not guessed at
+        assert!(color.set_from_map_value("1 0.5 0"));
+        assert_eq!(color, Color::srgb(1.0 / 255.0, 0.5 / 255.0, 0.0));
+
+        assert!(!color.set_from_map_value("256 0 0"));
+        assert!(!color.set_from_map_value("255 0"));
+    }
+
+    #[test]
+    fn colors_read_back_their_fgd_default() {
+        let color = Color::srgb(1.0, 0.5, 0.75);
+        let fgd = color.get_fgd_string("tint", "");
+        let default = fgd.split('"').nth(3).unwrap();
+        assert_eq!(default, "255 128 191");
+
+        let mut read = Color::BLACK;
+        assert!(read.set_from_map_value(default));
+        let (read, color) = (read.to_srgba(), color.to_srgba());
+        assert!((read.red - color.red).abs() < 1.0 / 255.0);
+        assert!((read.green - color.green).abs() < 1.0 / 255.0);
+        assert!((read.blue - color.blue).abs() < 1.0 / 255.0);
+    }
+
+    #[test]
+    fn choices_are_read_from_their_keys() {
+        let mut choice = Choices::default();
+        assert!(choice.set_from_map_value("second"));
+        assert_eq!(choice, Choices::Second);
+        assert!(choice.set_from_map_value(" 2 "));
+        assert_eq!(choice, Choices::Third);
+
+        // the overridden key replaces the index
+        assert!(!choice.set_from_map_value("1"));
+        assert_eq!(choice, Choices::Third);
+    }
+
+    #[test]
+    fn flags_are_read_from_a_single_bit() {
+        let mut flag = Flags::default();
+        assert!(flag.set_from_map_value("4"));
+        assert_eq!(flag, Flags::Third);
+        assert!(flag.set_from_map_value("2"));
+        assert_eq!(flag, Flags::Second);
+
+        // the enum can't hold several flags, or none
+        assert!(!flag.set_from_map_value("6"));
+        assert!(!flag.set_from_map_value("0"));
+        assert!(!flag.set_from_map_value("8"));
+        assert_eq!(flag, Flags::Second);
+    }
+}
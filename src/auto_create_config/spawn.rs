@@ -0,0 +1,190 @@
+use std::any::TypeId;
+
+use bevy::{
+    ecs::reflect::ReflectCommandExt,
+    prelude::*,
+    reflect::{ReflectMut, TypeRegistry},
+    utils::HashMap,
+};
+
+use crate::auto_create_config::register_types::{
+    entities::ReflectQevyEntity, properties::ReflectQevyProperty,
+};
+use crate::components::MapEntityProperties;
+use crate::PostBuildMapEvent;
+
+use super::QevyRegistry;
+
+/// Inserts the registered qevy entity matching the classname of every entity of a built map,
+/// with its fields read from the entity's properties.
+/// The type needs to reflect `Component` as well, e.g. `#[reflect(QevyEntity, Component, Default)]`.
+pub(crate) fn spawn_qevy_entities_system(
+    mut commands: Commands,
+    qevy_registry: Res<QevyRegistry>,
+    types: Res<AppTypeRegistry>,
+    mut post_build_map_event: EventReader<PostBuildMapEvent>,
+    map_entities: Query<(Entity, &MapEntityProperties, &Parent)>,
+) {
+    if post_build_map_event.is_empty() || qevy_registry.qevy_entities.is_empty() {
+        post_build_map_event.clear();
+        return;
+    }
+
+    let types = types.read();
+    let classnames = classnames(&qevy_registry, &types);
+
+    for ev in post_build_map_event.read() {
+        for (entity, props, parent) in map_entities.iter() {
+            if parent.get() != ev.map {
+                continue;
+            }
+            let Some(type_id) = classnames.get(props.classname.as_str()) else {
+                continue;
+            };
+            let registration = types.get(*type_id).unwrap();
+            if registration.data::<ReflectComponent>().is_none() {
+                warn!(
+                    "{} is registered for classname {}, but doesn't reflect Component",
+                    registration.type_info().type_path_table().short_path(),
+                    props.classname
+                );
+                continue;
+            }
+
+            let mut value = registration.data::<ReflectDefault>().unwrap().default();
+            apply_properties(value.as_partial_reflect_mut(), &types, props);
+            commands
+                .entity(entity)
+                .insert_reflect(value.into_partial_reflect());
+        }
+    }
+}
+
+/// The registered qevy entities by classname, base classes can't be spawned
+fn classnames(qevy_registry: &QevyRegistry, types: &TypeRegistry) -> HashMap<&'static str, TypeId> {
+    qevy_registry
+        .qevy_entities
+        .iter()
+        .filter_map(|type_id| {
+            let registration = types.get(*type_id)?;
+            let default_value = registration.data::<ReflectDefault>()?.default();
+            let qevy_entity = registration
+                .data::<ReflectQevyEntity>()?
+                .get(&*default_value)?;
+            Some((qevy_entity.get_classname(), *type_id))
+        })
+        .collect()
+}
+
+/// Sets the fields of a qevy entity from the map's properties. Base class fields are
+/// read from the same properties, since base classes only add keys to the entity.
+fn apply_properties(
+    value: &mut dyn PartialReflect,
+    types: &TypeRegistry,
+    props: &MapEntityProperties,
+) {
+    let ReflectMut::Struct(value) = value.reflect_mut() else {
+        return;
+    };
+
+    for i in 0..value.field_len() {
+        let field_name = value.name_at(i).unwrap().to_string();
+        let field = value.field_at_mut(i).unwrap();
+        let Some(registration) = field
+            .get_represented_type_info()
+            .and_then(|type_info| types.get(type_info.type_id()))
+        else {
+            continue;
+        };
+
+        if registration.data::<ReflectQevyEntity>().is_some() {
+            apply_properties(field, types, props);
+            continue;
+        }
+
+        let Some(property) = registration
+            .data::<ReflectQevyProperty>()
+            .zip(field.try_as_reflect_mut())
+            .and_then(|(reflect_property, field)| reflect_property.get_mut(field))
+        else {
+            continue;
+        };
+        let key = property.get_map_key(&field_name);
+        let Some(map_value) = props.properties.get(key) else {
+            continue;
+        };
+        if !property.set_from_map_value(map_value) {
+            warn!(
+                "{} has an invalid `{}` property: \"{}\"",
+                props.classname, key, map_value
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use bevy::reflect::ReflectMut;
+    use qevy_derive::QevyEntity;
+
+    use super::*;
+    use crate::auto_create_config::register_types::{
+        entities::{QevyEntity, QevyRegisterSolidClass},
+        properties::{QevyProperty, QevyPropertyPlugin},
+    };
+
+    #[derive(Reflect, Default, Debug, PartialEq, QevyProperty)]
+    #[reflect(QevyProperty, Default)]
+    #[qevy_property(property_type = "choices")]
+    enum Mood {
+        #[default]
+        #[qevy_property(selected_by_default = true)]
+        Calm,
+        Angry,
+    }
+
+    #[derive(Component, Reflect, Default, QevyEntity)]
+    #[reflect(QevyEntity, Component, Default)]
+    #[qevy_entity(entity_type = "Point", entity_name = "monster_test")]
+    struct Monster {
+        health: u32,
+        mood: Mood,
+        tint: Color,
+    }
+
+    #[test]
+    fn spawns_registered_entities_from_their_properties() {
+        let mut app = App::new();
+        app.add_plugins(QevyPropertyPlugin)
+            .init_resource::<QevyRegistry>()
+            .add_event::<PostBuildMapEvent>()
+            .register_type::<Mood>()
+            .register_qevy_entity::<Monster>()
+            .add_systems(Update, spawn_qevy_entities_system);
+
+        let properties = |classname: &str| MapEntityProperties {
+            classname: classname.into(),
+            properties: BTreeMap::from(
+                [("health", "50"), ("mood", "1"), ("tint", "255 0 0")]
+                    .map(|(key, value)| (key.to_string(), value.to_string())),
+            ),
+            ..default()
+        };
+        let map = app.world_mut().spawn_empty().id();
+        let mut children = Vec::new();
+        app.world_mut().entity_mut(map).with_children(|parent| {
+            children.push(parent.spawn(properties("monster_test")).id());
+            children.push(parent.spawn(properties("info_null")).id());
+        });
+        app.world_mut().send_event(PostBuildMapEvent { map });
+        app.update();
+
+        let monster = app.world().get::<Monster>(children[0]).unwrap();
+        assert_eq!(monster.health, 50);
+        assert_eq!(monster.mood, Mood::Angry);
+        assert_eq!(monster.tint, Color::srgb(1.0, 0.0, 0.0));
+        assert!(app.world().get::<Monster>(children[1]).is_none());
+    }
+}
@@ -1,3 +1,4 @@
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 use bevy::render::mesh::Indices;
 use bevy::render::render_asset::RenderAssetUsages;
@@ -7,42 +8,64 @@ use bevy::utils::HashMap;
 #[cfg(feature = "rapier")]
 #[cfg(not(feature = "avian"))]
 use bevy_rapier3d::geometry::ActiveCollisionTypes;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::time::Duration;
 
+use crate::classes::MapClassRegistry;
 use crate::components::*;
 use crate::conversions::*;
+use crate::material::MapMaterialFactory;
+use crate::normals::DEFAULT_PHONG_ANGLE;
+use crate::paths::{PathCorner, PathGraph};
+use crate::settings::MeshBatching;
+use crate::textures::TextureRules;
 
-use crate::{MapAsset, PostBuildMapEvent};
+use crate::{MapAsset, MapAssetLoaderError, MapBuildErrorEvent, PostBuildMapEvent};
+use shambler::entity::EntityId;
 
 #[derive(Event)]
 pub struct SpawnMeshEvent {
     map: Entity,
     mesh: Mesh,
     collider: Option<Entity>,
-    material: Handle<StandardMaterial>,
+    texture_name: String,
+}
+
+/// The settings of `MapAssetLoaderPlugin` maps are built with
+#[derive(SystemParam)]
+pub struct MapBuildSettings<'w> {
+    pub map_units: Res<'w, MapUnits>,
+    pub texture_rules: Res<'w, TextureRules>,
+    pub default_collider: Res<'w, ColliderShape>,
+    pub classes: Res<'w, MapClassRegistry>,
+}
+
+/// The events sent while building a map
+#[derive(SystemParam)]
+pub struct MapBuildEvents<'w> {
+    pub spawn_mesh: EventWriter<'w, SpawnMeshEvent>,
+    pub post_build_map: EventWriter<'w, PostBuildMapEvent>,
+    pub build_error: EventWriter<'w, MapBuildErrorEvent>,
 }
 
 pub fn build_map(
-    map_units: &MapUnits,
+    settings: &MapBuildSettings,
     map_entity: Entity,
-    map_asset: &mut MapAsset,
+    map_asset: &MapAsset,
     commands: &mut Commands,
-    spawn_mesh_event: &mut EventWriter<SpawnMeshEvent>,
-    post_build_map_event: &mut EventWriter<PostBuildMapEvent>,
-) {
-    let geomap = map_asset.geomap.as_mut().unwrap();
-    // Filter out nodraw faces
-    geomap.faces.retain(|face_id| {
-        let texture_id = geomap.face_textures.get(face_id).unwrap();
-        let texture_name = geomap.textures.get(texture_id).unwrap();
-        texture_name != "__TB_empty"
-    });
-    // geomap doesn't need to be mut any more
+    events: &mut MapBuildEvents,
+) -> Vec<MapAssetLoaderError> {
+    let map_units: &MapUnits = &settings.map_units;
+    let texture_rules: &TextureRules = &settings.texture_rules;
+    let default_collider = *settings.default_collider;
+    let classes: &MapClassRegistry = &settings.classes;
+    let spawn_mesh_event = &mut events.spawn_mesh;
+    let mut errors = Vec::new();
+    // the asset is left untouched so rebuilding a map (or building it for another `Map`) gives the same result
     let geomap = map_asset.geomap.as_ref().unwrap();
 
     let face_trangle_planes = &geomap.face_planes;
-    let face_planes = shambler::face::face_planes(&face_trangle_planes);
+    let face_planes = shambler::face::face_planes(face_trangle_planes);
     let brush_hulls = shambler::brush::brush_hulls(&geomap.brush_faces, &face_planes);
     let (face_vertices, _face_vertex_planes) =
         shambler::face::face_vertices(&geomap.brush_faces, &face_planes, &brush_hulls);
@@ -57,21 +80,16 @@ pub fn build_map(
     let face_triangle_indices = shambler::face::face_triangle_indices(&face_indices);
     let face_normals = shambler::face::normals_flat(&face_vertices, &face_planes);
 
-    let face_uvs = shambler::face::new(
-        &geomap.faces,
-        &geomap.textures,
-        &geomap.face_textures,
+    let face_uvs = crate::uv::face_uvs(
+        geomap,
         &face_vertices,
         &face_planes,
-        &geomap.face_offsets,
-        &geomap.face_angles,
-        &geomap.face_scales,
-        &shambler::texture::texture_sizes(
-            &geomap.textures,
-            map_asset.get_texture_names_with_size(),
-        ),
+        &map_asset.get_texture_names_with_size(),
     );
 
+    // path corners are linked together once they're all spawned
+    let mut path_corners = Vec::<(Entity, PathCorner, Option<String>)>::new();
+
     // spawn entities (@PointClass)
     geomap
         .entity_properties
@@ -90,32 +108,23 @@ pub fn build_map(
                 .collect::<BTreeMap<_, _>>();
 
             let classname = props.get(&"classname").unwrap_or(&"").to_string();
-            let translation = props.get(&"origin").unwrap_or(&"0 0 0").to_string();
-            let rotation = props.get(&"angles").unwrap_or(&"0 0 0").to_string();
-
-            let translation = translation.split(" ").collect::<Vec<&str>>();
-            let translation = if translation.len() == 3 {
-                to_bevy_position(
-                    &Vec3::new(
-                        translation[0].parse::<f32>().unwrap(),
-                        translation[1].parse::<f32>().unwrap(),
-                        translation[2].parse::<f32>().unwrap(),
-                    ),
-                    &map_units,
-                )
-            } else {
-                Vec3::ZERO
-            };
 
-            let rotation = rotation.split(" ").collect::<Vec<&str>>();
-            let rotation = if rotation.len() == 3 {
-                to_bevy_rotation(&Vec3::new(
-                    rotation[0].parse::<f32>().unwrap(),
-                    rotation[1].parse::<f32>().unwrap(),
-                    rotation[2].parse::<f32>().unwrap(),
-                ))
-            } else {
-                Quat::IDENTITY
+            let (translation, rotation) = match (
+                parse_vec3_property(entity_id, &classname, &props, "origin"),
+                parse_vec3_property(entity_id, &classname, &props, "angles"),
+            ) {
+                (Ok(translation), Ok(rotation)) => (
+                    translation
+                        .map(|translation| to_bevy_position(&translation, map_units))
+                        .unwrap_or(Vec3::ZERO),
+                    rotation
+                        .map(|rotation| to_bevy_rotation(&rotation))
+                        .unwrap_or(Quat::IDENTITY),
+                ),
+                (Err(e), _) | (_, Err(e)) => {
+                    errors.push(e);
+                    return;
+                }
             };
 
             commands.entity(map_entity).with_children(|children| {
@@ -132,221 +141,796 @@ pub fn build_map(
                 if let Some(target_name) = props.get("targetname") {
                     entity.insert(TriggerTarget {
                         target_name: target_name.to_string(),
+                        map: Some(map_entity),
                     });
                 }
+
+                if classname == "path_corner" {
+                    let wait = props
+                        .get("wait")
+                        .and_then(|wait| wait.trim().parse::<f32>().ok())
+                        .unwrap_or(0.0);
+                    let corner = PathCorner {
+                        name: props.get("targetname").unwrap_or(&"").to_string(),
+                        map: map_entity,
+                        position: translation,
+                        next: None,
+                        wait: (wait >= 0.0).then(|| Duration::from_secs_f32(wait)),
+                        pathtarget: props
+                            .get("pathtarget")
+                            .filter(|pathtarget| !pathtarget.is_empty())
+                            .map(|pathtarget| pathtarget.to_string()),
+                    };
+                    let target = props.get("target").map(|target| target.to_string());
+                    path_corners.push((entity.id(), corner, target));
+                }
             });
         });
 
+    let corner_names = path_corners
+        .iter()
+        .map(|(entity, corner, _)| (corner.name.clone(), *entity))
+        .collect::<HashMap<_, _>>();
+    let path_corners = path_corners
+        .into_iter()
+        .map(|(entity, mut corner, target)| {
+            corner.next = target.and_then(|target| corner_names.get(&target).copied());
+            (entity, corner)
+        })
+        .collect::<Vec<_>>();
+    commands.queue(move |world: &mut World| {
+        world
+            .get_resource_or_insert_with(PathGraph::default)
+            .insert_map(map_entity, path_corners);
+    });
+
+    // faces of world brushes buried in other world brushes, they only get a collider
+    let hidden_faces = if map_asset.remove_hidden_faces {
+        let world_brushes = geomap
+            .entity_brushes
+            .iter()
+            .filter(|(entity_id, _)| {
+                geomap
+                    .entity_properties
+                    .get(entity_id)
+                    .and_then(|props| props.iter().find(|p| p.key == "classname"))
+                    .is_some_and(|p| map_asset.world_classnames.contains(&p.value))
+            })
+            .flat_map(|(_, brushes)| brushes.iter().copied())
+            .collect::<Vec<_>>();
+        let occluders = world_brushes
+            .iter()
+            .filter(|brush_id| {
+                geomap.brush_faces[brush_id].iter().all(|face_id| {
+                    is_opaque_texture(
+                        texture_rules,
+                        &geomap.textures[&geomap.face_textures[face_id]],
+                    )
+                })
+            })
+            .copied()
+            .collect::<Vec<_>>();
+        crate::culling::hidden_faces(
+            &world_brushes,
+            &occluders,
+            &geomap.brush_faces,
+            &face_vertices,
+            &face_planes,
+        )
+    } else {
+        BTreeSet::new()
+    };
+
+    // faces of the static world brushes, merged across brushes by texture and chunk
+    let mut batched_meshes = HashMap::<(String, IVec3), Mesh>::new();
+
     // spawn brush entities (@SolidClass)
     for (entity_id, brushes) in geomap.entity_brushes.iter() {
-        let entity_properties = geomap.entity_properties.get(&entity_id);
-
-        if let None = entity_properties {
-            panic!("brush entity {} has no properties!", entity_id);
-        }
+        let Some(entity_properties) = geomap.entity_properties.get(entity_id) else {
+            errors.push(MapAssetLoaderError::MissingEntityProperties {
+                entity: entity_id.0,
+            });
+            continue;
+        };
 
         // map properties into btree
         // just easier to access props
         let mut props = entity_properties
-            .unwrap()
             .iter()
             .map(|p| (p.key.as_str(), p.value.as_str()))
             .collect::<BTreeMap<_, _>>();
         let classname = props.get(&"classname").unwrap_or(&"").to_string();
+        let batched = map_asset.batching != MeshBatching::PerBrush
+            && map_asset.world_classnames.contains(&classname);
+        let collider_shape = match props.get("_collider") {
+            Some(name) => ColliderShape::from_name(name).unwrap_or_else(|| {
+                errors.push(MapAssetLoaderError::InvalidEntityProperty {
+                    entity: entity_id.0,
+                    classname: classname.clone(),
+                    key: "_collider".to_string(),
+                    value: name.to_string(),
+                });
+                default_collider
+            }),
+            None => default_collider,
+        };
+
+        // the entity is placed on what it turns about, the `origin` key winning over an origin brush.
+        // the origin brush only marks that point, it isn't built
+        let origin_brush = brushes
+            .iter()
+            .copied()
+            .find(|brush_id| is_origin_brush(&geomap.brush_faces[brush_id], geomap, texture_rules));
+        let pivot = match parse_vec3_property(entity_id, &classname, &props, "origin") {
+            Ok(Some(origin)) => Some(to_bevy_position(&origin, map_units)),
+            Ok(None) => origin_brush.map(|brush_id| {
+                let (min, max) = geomap.brush_faces[&brush_id]
+                    .iter()
+                    .filter_map(|face_id| face_vertices.get(face_id))
+                    .flat_map(|vertices| to_bevy_vertices(vertices, map_units))
+                    .fold((Vec3::MAX, Vec3::MIN), |(min, max), vertex| {
+                        (min.min(vertex), max.max(vertex))
+                    });
+                (min + max) / 2.0
+            }),
+            Err(e) => {
+                errors.push(e);
+                None
+            }
+        };
+        let offset = pivot.unwrap_or(Vec3::ZERO);
+        let transform = Transform::from_translation(offset);
+
         let brush_entity = (
             BrushEntity {},
+            transform,
             MapEntityProperties {
                 classname: classname.to_string(),
+                transform,
                 properties: props
                     .iter_mut()
                     .map(|(k, v)| (k.to_string(), v.to_string()))
                     .collect(),
-                ..default()
             },
         );
 
+        // smooth shading across all the brushes of the entity, same keys as ericw-tools
+        let phong_normals = if props.get("_phong").map(|v| v.trim()) == Some("1") {
+            let faces = brushes
+                .iter()
+                .flat_map(|brush_id| geomap.brush_faces[brush_id].iter().copied())
+                .filter(|face_id| {
+                    texture_rules
+                        .get(&geomap.textures[&geomap.face_textures[face_id]])
+                        .render
+                        && !hidden_faces.contains(face_id)
+                })
+                .collect::<Vec<_>>();
+            let angle = props
+                .get("_phong_angle")
+                .and_then(|angle| angle.trim().parse::<f32>().ok())
+                .unwrap_or(DEFAULT_PHONG_ANGLE);
+            crate::normals::phong_normals(&faces, &face_vertices, &face_planes, angle)
+        } else {
+            BTreeMap::new()
+        };
+
         commands.entity(map_entity).with_children(|children| {
             let mut entity = children.spawn(brush_entity);
+            let mut bounds: Option<BrushBounds> = None;
+            let mut liquid_colliders = Vec::new();
             entity.with_children(|gchildren| {
+                let mut merged_brushes = Vec::<MergedBrushes>::new();
                 for brush_id in brushes.iter() {
+                    if Some(*brush_id) == origin_brush {
+                        continue;
+                    }
                     let brush_faces = geomap.brush_faces.get(brush_id).unwrap();
                     let mut brush_vertices: Vec<Vec3> = Vec::new();
+                    let mut brush_triangles: Vec<[u32; 3]> = Vec::new();
                     let mut meshes_to_spawn = HashMap::<String, Mesh>::new();
+                    // batched faces only join the map's meshes once the brush is kept
+                    let mut batched_faces = HashMap::<(String, IVec3), Mesh>::new();
+                    // what the textures of its faces make of the brush's collider
+                    let mut collides = true;
+                    let mut collision_layers = None;
+                    let mut sky = false;
+                    let mut liquid = None;
+
+                    let degenerate_brush = MapAssetLoaderError::DegenerateBrush {
+                        entity: entity_id.0,
+                        brush: brush_id.0,
+                        classname: classname.clone(),
+                    };
+                    let polygon_vertices: usize = brush_faces
+                        .iter()
+                        .filter_map(|face_id| face_vertices.get(face_id))
+                        .filter(|vertices| vertices.len() >= 3)
+                        .map(|vertices| vertices.len())
+                        .sum();
+                    if polygon_vertices < 4 {
+                        errors.push(degenerate_brush);
+                        continue;
+                    }
 
                     for face_id in brush_faces.iter() {
                         let texture_id = geomap.face_textures.get(face_id).unwrap();
                         let texture_name = geomap.textures.get(texture_id).unwrap();
-                        
-                        let indices =
-                            to_bevy_indecies(&face_triangle_indices.get(&face_id).unwrap());
-                        let vertices =
-                            to_bevy_vertices(&face_vertices.get(&face_id).unwrap(), &map_units);
-                        brush_vertices.extend(vertices.clone());
-
-                        // we don't render anything for these textures
-                        if texture_name == "trigger"
-                            || texture_name == "clip"
-                            || texture_name == "common/trigger"
-                            || texture_name == "common/clip"
-                            || texture_name == "__TB_empty"
-                        {
+
+                        // planes that don't touch the brush hull end up without a polygon
+                        let (Some(face_vertices), Some(face_triangle_indices)) = (
+                            face_vertices.get(face_id),
+                            face_triangle_indices.get(face_id),
+                        ) else {
+                            continue;
+                        };
+                        if face_vertices.len() < 3 {
                             continue;
                         }
+                        let behavior = texture_rules.get(texture_name);
+                        collides &= behavior.collide;
+                        collision_layers = collision_layers.or(behavior.collision_layers);
+                        sky |= behavior.sky;
+                        liquid = liquid.or(behavior.liquid);
+
+                        let indices = to_bevy_indecies(face_triangle_indices);
+                        // relative to the entity, batched faces stay in map space
+                        let map_vertices = to_bevy_vertices(face_vertices, map_units);
+                        let vertices = map_vertices
+                            .iter()
+                            .map(|vertex| *vertex - offset)
+                            .collect::<Vec<_>>();
+                        let first_vertex = brush_vertices.len() as u32;
+                        brush_triangles.extend(
+                            indices
+                                .chunks_exact(3)
+                                .map(|triangle| [0, 1, 2].map(|i| triangle[i] + first_vertex)),
+                        );
+                        brush_vertices.extend(vertices.iter().copied());
 
-                        let normals = to_bevy_vec3s(&face_normals.get(&face_id).unwrap());
-                        let uvs = uvs_to_bevy_vec2s(&face_uvs.get(&face_id).unwrap());
+                        if !behavior.render || hidden_faces.contains(face_id) {
+                            continue;
+                        }
+
+                        let normals = to_bevy_vec3s(
+                            phong_normals
+                                .get(face_id)
+                                .or_else(|| face_normals.get(face_id))
+                                .unwrap(),
+                        );
+                        let uvs = face_uvs.get(face_id).cloned().unwrap_or_default();
 
                         let mut mesh = Mesh::new(
                             PrimitiveTopology::TriangleList,
                             RenderAssetUsages::RENDER_WORLD,
                         );
-                        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vertices);
+                        mesh.insert_attribute(
+                            Mesh::ATTRIBUTE_POSITION,
+                            if batched { map_vertices } else { vertices },
+                        );
                         mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
                         mesh.insert_indices(Indices::U32(indices));
 
-                        if uvs.len() > 0 {
+                        if !uvs.is_empty() {
                             mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
                             if let Err(e) = mesh.generate_tangents() {
-                                println!("error generating tangents: {:?}", e);
+                                warn!("error generating tangents: {:?}", e);
                             }
                         }
 
-                        match meshes_to_spawn.entry(texture_name.clone()) {
-                            Entry::Occupied(mut entry) => {
-                                let existing_mesh = entry.get_mut();
-                                existing_mesh.merge(&mesh);
-                            }
-                            Entry::Vacant(entry) => {
-                                entry.insert(mesh);
-                            }
+                        if batched {
+                            let center = face_centers[face_id];
+                            let chunk = map_asset
+                                .batching
+                                .chunk(Vec3::new(center.x, center.y, center.z))
+                                .unwrap();
+                            merge_mesh(&mut batched_faces, (texture_name.clone(), chunk), mesh);
+                        } else {
+                            merge_mesh(&mut meshes_to_spawn, texture_name.clone(), mesh);
                         }
                     }
 
-                    // spawn it's collider
-                    #[cfg(feature = "avian")]
-                    {
-                        if let Some(convex_hull) =
-                            avian3d::prelude::Collider::convex_hull(brush_vertices)
-                        {
-                            let mut collider = gchildren.spawn((
-                                convex_hull,
-                                Transform::default(),
-                                Visibility::default(),
-                            ));
-                            if classname == "trigger_multiple" {
-                                collider.insert((
-                                    TriggerMultiple {
-                                        target: props.get("target").unwrap().to_string(),
-                                    },
-                                    avian3d::prelude::RigidBody::Dynamic,
-                                    avian3d::prelude::Sensor,
-                                ));
-                            } else if classname == "trigger_once" {
-                                collider.insert((
-                                    TriggerOnce {
-                                        target: props.get("target").unwrap().to_string(),
-                                    },
-                                    avian3d::prelude::RigidBody::Dynamic,
-                                    avian3d::prelude::Sensor,
-                                ));
-                            } else {
-                                collider.insert((avian3d::prelude::RigidBody::Static,));
-                            }
+                    for vertex in brush_vertices.iter() {
+                        let entity_bounds = bounds.get_or_insert(BrushBounds {
+                            min: *vertex,
+                            max: *vertex,
+                        });
+                        entity_bounds.min = entity_bounds.min.min(*vertex);
+                        entity_bounds.max = entity_bounds.max.max(*vertex);
+                    }
 
-                            for (texture_name, mesh) in meshes_to_spawn {
-                                if map_asset.material_handles.contains_key(&texture_name) {
-                                    spawn_mesh_event.send(SpawnMeshEvent {
-                                        map: map_entity,
-                                        mesh: mesh,
-                                        collider: Some(collider.id()),
-                                        material: map_asset
-                                            .material_handles
-                                            .get(&texture_name)
-                                            .unwrap()
-                                            .clone(),
-                                    });
-                                }
-                            }
+                    // brushes that don't collide still need an entity for their meshes
+                    if !collides || collider_shape == ColliderShape::None {
+                        let parent = gchildren
+                            .spawn((Transform::default(), Visibility::default()))
+                            .id();
+                        send_meshes(
+                            spawn_mesh_event,
+                            map_asset,
+                            map_entity,
+                            meshes_to_spawn,
+                            parent,
+                        );
+                        for (key, mesh) in batched_faces {
+                            merge_mesh(&mut batched_meshes, key, mesh);
                         }
+                        continue;
                     }
 
-                    #[cfg(feature = "rapier")]
-                    #[cfg(not(feature = "avian"))]
-                    {
-                        if let Some(convex_hull) =
-                            bevy_rapier3d::prelude::Collider::convex_hull(&brush_vertices)
-                        {
-                            let mut collider = gchildren.spawn((
-                                convex_hull,
-                                Transform::default(),
-                                Visibility::default(),
-                            ));
-                            if classname == "trigger_multiple" {
-                                collider.insert((
-                                    TriggerMultiple {
-                                        target: props.get("target").unwrap().to_string(),
-                                    },
-                                    bevy_rapier3d::prelude::RigidBody::KinematicPositionBased,
-                                    bevy_rapier3d::prelude::Sensor,
-                                    ActiveCollisionTypes::default()
-                                        | ActiveCollisionTypes::KINEMATIC_KINEMATIC,
-                                ));
-                            } else if classname == "trigger_once" {
-                                collider.insert((
-                                    TriggerOnce {
-                                        target: props.get("target").unwrap().to_string(),
-                                    },
-                                    bevy_rapier3d::prelude::RigidBody::KinematicPositionBased,
-                                    bevy_rapier3d::prelude::Sensor,
-                                    ActiveCollisionTypes::default()
-                                        | ActiveCollisionTypes::KINEMATIC_KINEMATIC,
-                                ));
-                            } else {
-                                collider.insert((bevy_rapier3d::prelude::RigidBody::Fixed,));
-                            }
-
-                            for (texture_name, mesh) in meshes_to_spawn {
-                                if map_asset.material_handles.contains_key(&texture_name) {
-                                    spawn_mesh_event.send(SpawnMeshEvent {
-                                        map: map_entity,
-                                        mesh: mesh,
-                                        collider: Some(collider.id()),
-                                        material: map_asset
-                                            .material_handles
-                                            .get(&texture_name)
-                                            .unwrap()
-                                            .clone(),
-                                    });
-                                }
+                    // liquid brushes of other entities become volumes of their own, their collider going under them
+                    let liquid_volume =
+                        liquid.filter(|_| !is_liquid_class(&classname)).map(|kind| {
+                            let (min, max) = brush_vertices
+                                .iter()
+                                .fold((Vec3::MAX, Vec3::MIN), |(min, max), vertex| {
+                                    (min.min(*vertex), max.max(*vertex))
+                                });
+                            gchildren
+                                .spawn((LiquidVolume::new(kind), BrushBounds { min, max }))
+                                .id()
+                        });
+                    let sensor = is_trigger_class(&classname)
+                        || is_liquid_class(&classname)
+                        || liquid_volume.is_some();
+
+                    // brushes sharing a single collider are spawned once they're all known, liquid volumes keep their own
+                    if collider_shape != ColliderShape::Convex && liquid_volume.is_none() {
+                        let group = match merged_brushes.iter().position(|group| {
+                            group.collision_layers == collision_layers && group.sky == sky
+                        }) {
+                            Some(index) => &mut merged_brushes[index],
+                            None => {
+                                merged_brushes.push(MergedBrushes {
+                                    collision_layers,
+                                    sky,
+                                    ..default()
+                                });
+                                merged_brushes.last_mut().unwrap()
                             }
+                        };
+                        let first_vertex = group.vertices.len() as u32;
+                        group.vertices.extend(brush_vertices.iter().copied());
+                        group.triangles.extend(
+                            brush_triangles
+                                .iter()
+                                .map(|triangle| triangle.map(|index| index + first_vertex)),
+                        );
+                        group.hulls.push(brush_vertices);
+                        for (texture_name, mesh) in meshes_to_spawn {
+                            merge_mesh(&mut group.meshes, texture_name, mesh);
+                        }
+                        for (key, mesh) in batched_faces {
+                            merge_mesh(&mut batched_meshes, key, mesh);
                         }
+                        continue;
+                    }
+
+                    let Some(collider) = spawn_brush_collider(
+                        gchildren,
+                        BrushCollider {
+                            geometry: BrushGeometry::ConvexHull(brush_vertices),
+                            sensor,
+                            mover: classes.is_kinematic(&classname),
+                            collision_layers,
+                            sky,
+                        },
+                    ) else {
+                        errors.push(degenerate_brush);
+                        continue;
+                    };
+                    if let Some(liquid_volume) = liquid_volume {
+                        liquid_colliders.push((liquid_volume, collider));
+                    }
+                    send_meshes(
+                        spawn_mesh_event,
+                        map_asset,
+                        map_entity,
+                        meshes_to_spawn,
+                        collider,
+                    );
+                    for (key, mesh) in batched_faces {
+                        merge_mesh(&mut batched_meshes, key, mesh);
+                    }
+                }
+
+                for group in merged_brushes {
+                    let geometry = if collider_shape == ColliderShape::Trimesh {
+                        BrushGeometry::Trimesh(group.vertices, group.triangles)
+                    } else {
+                        BrushGeometry::Compound(group.hulls)
+                    };
+                    let collider = spawn_brush_collider(
+                        gchildren,
+                        BrushCollider {
+                            geometry,
+                            sensor: is_trigger_class(&classname) || is_liquid_class(&classname),
+                            mover: classes.is_kinematic(&classname),
+                            collision_layers: group.collision_layers,
+                            sky: group.sky,
+                        },
+                    );
+                    if let Some(collider) = collider {
+                        send_meshes(
+                            spawn_mesh_event,
+                            map_asset,
+                            map_entity,
+                            group.meshes,
+                            collider,
+                        );
                     }
                 }
             });
 
+            // moved once `with_children` added the colliders to the entity
+            for (liquid_volume, collider) in liquid_colliders {
+                entity.commands().entity(liquid_volume).add_child(collider);
+            }
+            if let Some(bounds) = bounds {
+                entity.insert(bounds);
+            }
+            // moved through the velocities of a single body, so they push and carry what they run into
+            #[cfg(feature = "avian")]
+            if classes.is_kinematic(&classname) {
+                entity.insert((
+                    avian3d::prelude::RigidBody::Kinematic,
+                    avian3d::prelude::LinearVelocity::default(),
+                    avian3d::prelude::AngularVelocity::default(),
+                ));
+            }
+            if pivot.is_some() {
+                entity.insert(BrushPivot(Vec3::ZERO));
+            }
             if let Some(target_name) = props.get("targetname") {
                 entity.insert(TriggerTarget {
                     target_name: target_name.to_string(),
+                    map: Some(map_entity),
                 });
             }
         });
     }
 
-    post_build_map_event.send(PostBuildMapEvent { map: map_entity });
+    for ((texture_name, _), mesh) in batched_meshes {
+        if map_asset.material_handles.contains_key(&texture_name) {
+            spawn_mesh_event.send(SpawnMeshEvent {
+                map: map_entity,
+                mesh,
+                collider: None,
+                texture_name,
+            });
+        }
+    }
+
+    events
+        .post_build_map
+        .send(PostBuildMapEvent { map: map_entity });
+
+    errors
+}
+
+fn merge_mesh<K: Eq + std::hash::Hash>(meshes: &mut HashMap<K, Mesh>, key: K, mesh: Mesh) {
+    match meshes.entry(key) {
+        Entry::Occupied(mut entry) => {
+            entry.get_mut().merge(&mesh);
+        }
+        Entry::Vacant(entry) => {
+            entry.insert(mesh);
+        }
+    }
+}
+
+/// Brush entities that are sensors instead of solid, `trigger_once`, `trigger_multiple` and any other `trigger_*` class
+fn is_trigger_class(classname: &str) -> bool {
+    classname.starts_with("trigger_")
 }
 
-pub fn mesh_spawn_system(
+/// Brush entities whose brushes are all liquid, `LiquidVolume` is added by their class handler
+fn is_liquid_class(classname: &str) -> bool {
+    classname == "func_water"
+}
+
+/// A brush only textured `origin`, marking the point its entity turns about
+fn is_origin_brush(
+    brush_faces: &[shambler::face::FaceId],
+    geomap: &shambler::GeoMap,
+    texture_rules: &TextureRules,
+) -> bool {
+    brush_faces.iter().all(|face_id| {
+        texture_rules
+            .get(&geomap.textures[&geomap.face_textures[face_id]])
+            .origin
+    })
+}
+
+/// The brushes of an entity going into the same compound or trimesh collider
+#[derive(Default)]
+struct MergedBrushes {
+    collision_layers: Option<u32>,
+    sky: bool,
+    hulls: Vec<Vec<Vec3>>,
+    vertices: Vec<Vec3>,
+    triangles: Vec<[u32; 3]>,
+    meshes: HashMap<String, Mesh>,
+}
+
+/// A collider for one or more brushes, relative to their entity
+struct BrushCollider {
+    geometry: BrushGeometry,
+    sensor: bool,
+    mover: bool,
+    collision_layers: Option<u32>,
+    sky: bool,
+}
+
+enum BrushGeometry {
+    ConvexHull(Vec<Vec3>),
+    /// the convex hulls of several brushes, the degenerate ones are left out
+    Compound(Vec<Vec<Vec3>>),
+    Trimesh(Vec<Vec3>, Vec<[u32; 3]>),
+}
+
+/// Spawns the collider of brushes, `None` if their geometry is degenerate
+#[cfg(feature = "avian")]
+fn spawn_brush_collider(children: &mut ChildBuilder, brush: BrushCollider) -> Option<Entity> {
+    let shape = match brush.geometry {
+        BrushGeometry::ConvexHull(vertices) => avian3d::prelude::Collider::convex_hull(vertices)?,
+        BrushGeometry::Compound(hulls) => {
+            let hulls = hulls
+                .into_iter()
+                .filter_map(avian3d::prelude::Collider::convex_hull)
+                .map(|hull| (Vec3::ZERO, Quat::IDENTITY, hull))
+                .collect::<Vec<_>>();
+            if hulls.is_empty() {
+                return None;
+            }
+            avian3d::prelude::Collider::compound(hulls)
+        }
+        BrushGeometry::Trimesh(vertices, triangles) => {
+            if triangles.is_empty() {
+                return None;
+            }
+            avian3d::prelude::Collider::trimesh(vertices, triangles)
+        }
+    };
+    let mut collider = children.spawn((shape, Transform::default(), Visibility::default()));
+    // the trigger components themselves are added by the class handlers
+    if brush.sensor {
+        // kinematic like with rapier, so a trigger doesn't fall and still sees kinematic instigators
+        collider.insert((
+            avian3d::prelude::RigidBody::Kinematic,
+            avian3d::prelude::Sensor,
+            avian3d::prelude::CollidingEntities::default(),
+        ));
+    } else if !brush.mover {
+        // the colliders of movers are part of the kinematic body of their entity
+        collider.insert((avian3d::prelude::RigidBody::Static,));
+    }
+    if let Some(layers) = brush.collision_layers {
+        collider.insert(avian3d::prelude::CollisionLayers::from_bits(
+            layers,
+            u32::MAX,
+        ));
+    }
+    if brush.sky {
+        collider.insert(SkyPortal);
+    }
+    Some(collider.id())
+}
+
+/// Spawns the collider of brushes, `None` if their geometry is degenerate
+#[cfg(feature = "rapier")]
+#[cfg(not(feature = "avian"))]
+fn spawn_brush_collider(children: &mut ChildBuilder, brush: BrushCollider) -> Option<Entity> {
+    let shape = match brush.geometry {
+        BrushGeometry::ConvexHull(vertices) => {
+            bevy_rapier3d::prelude::Collider::convex_hull(&vertices)?
+        }
+        BrushGeometry::Compound(hulls) => {
+            let hulls = hulls
+                .iter()
+                .filter_map(|hull| bevy_rapier3d::prelude::Collider::convex_hull(hull))
+                .map(|hull| (Vec3::ZERO, Quat::IDENTITY, hull))
+                .collect::<Vec<_>>();
+            if hulls.is_empty() {
+                return None;
+            }
+            bevy_rapier3d::prelude::Collider::compound(hulls)
+        }
+        BrushGeometry::Trimesh(vertices, triangles) => {
+            if triangles.is_empty() {
+                return None;
+            }
+            bevy_rapier3d::prelude::Collider::trimesh(vertices, triangles)
+        }
+    };
+    let mut collider = children.spawn((shape, Transform::default(), Visibility::default()));
+    // the trigger components themselves are added by the class handlers
+    if brush.sensor {
+        collider.insert((
+            bevy_rapier3d::prelude::RigidBody::KinematicPositionBased,
+            bevy_rapier3d::prelude::Sensor,
+            ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_KINEMATIC,
+        ));
+    } else if brush.mover {
+        collider.insert((bevy_rapier3d::prelude::RigidBody::KinematicPositionBased,));
+    } else {
+        collider.insert((bevy_rapier3d::prelude::RigidBody::Fixed,));
+    }
+    if let Some(layers) = brush.collision_layers {
+        collider.insert(bevy_rapier3d::prelude::CollisionGroups::new(
+            bevy_rapier3d::prelude::Group::from_bits_truncate(layers),
+            bevy_rapier3d::prelude::Group::ALL,
+        ));
+    }
+    if brush.sky {
+        collider.insert(SkyPortal);
+    }
+    Some(collider.id())
+}
+
+/// Without a physics engine brushes only get an entity for their meshes
+#[cfg(not(any(feature = "rapier", feature = "avian")))]
+fn spawn_brush_collider(children: &mut ChildBuilder, _brush: BrushCollider) -> Option<Entity> {
+    Some(
+        children
+            .spawn((Transform::default(), Visibility::default()))
+            .id(),
+    )
+}
+
+/// Sends the meshes of brushes to be spawned under the entity holding them
+fn send_meshes(
+    spawn_mesh_event: &mut EventWriter<SpawnMeshEvent>,
+    map_asset: &MapAsset,
+    map_entity: Entity,
+    meshes: HashMap<String, Mesh>,
+    parent: Entity,
+) {
+    for (texture_name, mesh) in meshes {
+        if map_asset.material_handles.contains_key(&texture_name) {
+            spawn_mesh_event.send(SpawnMeshEvent {
+                map: map_entity,
+                mesh,
+                collider: Some(parent),
+                texture_name,
+            });
+        }
+    }
+}
+
+/// A box sensor seen by the trigger systems like the colliders of trigger brushes, for triggers spawned by the game
+#[cfg(feature = "avian")]
+pub(crate) fn trigger_box_collider(half_extents: Vec3) -> impl Bundle {
+    (
+        avian3d::prelude::Collider::cuboid(
+            half_extents.x * 2.0,
+            half_extents.y * 2.0,
+            half_extents.z * 2.0,
+        ),
+        avian3d::prelude::RigidBody::Kinematic,
+        avian3d::prelude::Sensor,
+        avian3d::prelude::CollidingEntities::default(),
+    )
+}
+
+#[cfg(feature = "rapier")]
+#[cfg(not(feature = "avian"))]
+pub(crate) fn trigger_box_collider(half_extents: Vec3) -> impl Bundle {
+    (
+        bevy_rapier3d::prelude::Collider::cuboid(half_extents.x, half_extents.y, half_extents.z),
+        bevy_rapier3d::prelude::RigidBody::KinematicPositionBased,
+        bevy_rapier3d::prelude::Sensor,
+        ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_KINEMATIC,
+    )
+}
+
+#[cfg(not(any(feature = "rapier", feature = "avian")))]
+pub(crate) fn trigger_box_collider(_half_extents: Vec3) -> impl Bundle {}
+
+/// Textures that can't be seen through, so brushes using only these hide the faces inside them.
+/// Textures that aren't rendered don't count, neither do the ones the rules don't make `opaque`
+fn is_opaque_texture(texture_rules: &TextureRules, texture_name: &str) -> bool {
+    let behavior = texture_rules.get(texture_name);
+    behavior.render && behavior.opaque
+}
+
+/// Parses an `x y z` property, `Ok(None)` if the entity doesn't have it
+fn parse_vec3_property(
+    entity_id: &EntityId,
+    classname: &str,
+    props: &BTreeMap<&str, &str>,
+    key: &str,
+) -> Result<Option<Vec3>, MapAssetLoaderError> {
+    let Some(value) = props.get(key) else {
+        return Ok(None);
+    };
+
+    let components = value
+        .split_whitespace()
+        .map(|component| component.parse::<f32>())
+        .collect::<Result<Vec<_>, _>>();
+
+    match components.as_deref() {
+        Ok([x, y, z]) => Ok(Some(Vec3::new(*x, *y, *z))),
+        _ => Err(MapAssetLoaderError::InvalidEntityProperty {
+            entity: entity_id.0,
+            classname: classname.to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+        }),
+    }
+}
+
+/// The materials made by the factory for each texture of a map
+type MaterialCache<M> = HashMap<(AssetId<MapAsset>, String), Option<Handle<M>>>;
+
+/// The materials of map faces, made by `F` once per texture of each map
+#[derive(SystemParam)]
+pub struct MapMaterials<'w, 's, F: MapMaterialFactory> {
+    factory: Res<'w, F>,
+    materials: ResMut<'w, Assets<<F as MapMaterialFactory>::Material>>,
+    map_assets: Res<'w, Assets<MapAsset>>,
+    ev_asset: EventReader<'w, 's, AssetEvent<MapAsset>>,
+    cache: Local<'s, MaterialCache<<F as MapMaterialFactory>::Material>>,
+}
+
+impl<F: MapMaterialFactory> MapMaterials<'_, '_, F> {
+    // materials are made again when the map is reloaded,
+    // the handles are let go of with the map so its materials can be unloaded
+    fn forget_unloaded_maps(&mut self) {
+        for ev in self.ev_asset.read() {
+            if let AssetEvent::Modified { id }
+            | AssetEvent::Removed { id }
+            | AssetEvent::Unused { id } = ev
+            {
+                self.cache.retain(|(map_asset_id, _), _| map_asset_id != id);
+            }
+        }
+        let map_assets = &self.map_assets;
+        self.cache
+            .retain(|(map_asset_id, _), _| map_assets.contains(*map_asset_id));
+    }
+
+    fn get(&mut self, id: AssetId<MapAsset>, texture_name: &str) -> Option<Handle<F::Material>> {
+        let Self {
+            factory,
+            materials,
+            map_assets,
+            cache,
+            ..
+        } = self;
+        cache
+            .entry((id, texture_name.to_string()))
+            .or_insert_with(|| {
+                let texture = map_assets.get(id)?.get_texture(texture_name)?;
+                factory.create_material(&texture, materials)
+            })
+            .clone()
+    }
+}
+
+pub fn mesh_spawn_system<F: MapMaterialFactory>(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: MapMaterials<F>,
+    q_maps: Query<&Map>,
     mut spawn_mesh_event: EventReader<SpawnMeshEvent>,
 ) {
+    materials.forget_unloaded_maps();
+
     for ev in spawn_mesh_event.read() {
+        let Ok(map) = q_maps.get(ev.map) else {
+            continue;
+        };
+        let Some(material) = materials.get(map.asset.id(), &ev.texture_name) else {
+            continue;
+        };
+
         // if this mesh has a collider, make it a child of the collider
         if let Some(collider) = ev.collider {
             commands.entity(collider).with_children(|children| {
                 children.spawn((
                     Mesh3d(meshes.add(ev.mesh.to_owned())),
-                    MeshMaterial3d(ev.material.to_owned()),
+                    MeshMaterial3d(material),
                 ));
             });
         // otherwise, it's a child of the map
@@ -354,84 +938,31 @@ pub fn mesh_spawn_system(
             commands.entity(ev.map).with_children(|children| {
                 children.spawn((
                     Mesh3d(meshes.add(ev.mesh.to_owned())),
-                    MeshMaterial3d(ev.material.to_owned()),
+                    MeshMaterial3d(material),
                 ));
             });
         }
     }
 }
 
+/// Sets up the entities Qevy knows about, this is now done by the handlers of the `MapClassRegistry`
+/// as soon as a map is built, so this system isn't needed anymore.
+#[deprecated(
+    note = "Qevy now sets up its entities through `classes::MapClassRegistry`, register your own classes with `MapClassApp::register_map_class` instead."
+)]
 pub fn post_build_map_system(
     map_units: Res<MapUnits>,
     mut commands: Commands,
     mut event_reader: EventReader<crate::PostBuildMapEvent>,
     mut map_entities: Query<(Entity, &crate::components::MapEntityProperties)>,
 ) {
+    let mut registry = crate::classes::MapClassRegistry::default();
+    crate::classes::register_builtin_classes(&mut registry);
+
     for _ in event_reader.read() {
-        // to set these up, see the .fgd file in the TrenchBroom
-        // game folder for Qevy Example also see the readme
         for (entity, props) in map_entities.iter_mut() {
-            match props.classname.as_str() {
-                "light" => {
-                    commands.entity(entity).insert((
-                        props.transform,
-                        PointLight {
-                            color: props.get_property_as_color("color", Color::WHITE),
-                            radius: props.get_property_as_f32("radius", 0.0),
-                            range: props.get_property_as_f32("range", 10.0),
-                            intensity: props.get_property_as_f32("intensity", 800.0),
-                            shadows_enabled: props.get_property_as_bool("shadows_enabled", false),
-                            ..default()
-                        },
-                    ));
-                }
-                "directional_light" => {
-                    commands.entity(entity).insert((
-                        props.transform,
-                        DirectionalLight {
-                            color: props.get_property_as_color("color", Color::WHITE),
-                            illuminance: props.get_property_as_f32("illuminance", 10000.0),
-                            shadows_enabled: props.get_property_as_bool("shadows_enabled", false),
-                            ..default()
-                        },
-                    ));
-                }
-                "mover" => {
-                    let mut mover_entity = commands.entity(entity);
-                    mover_entity.insert((
-                        Mover {
-                            moving_time: Duration::from_secs_f32(
-                                props.get_property_as_f32("moving_time", 1.0),
-                            ),
-                            destination_time: Duration::from_secs_f32(
-                                props.get_property_as_f32("destination_time", 2.0),
-                            ),
-                            destination_offset: {
-                                to_bevy_position(
-                                    &props.get_property_as_vec3("destination_offset", Vec3::ZERO),
-                                    &map_units,
-                                )
-                            },
-                            state: MoverState::default(),
-                        },
-                        Transform::from_xyz(0.0, 0.0, 0.0),
-                    ));
-
-                    if let Some(mover_kind) =
-                        props.get_property_as_string("mover_kind", Some(&"linear".into()))
-                    {
-                        match mover_kind.as_str() {
-                            "door" => {
-                                mover_entity.insert(Door {
-                                    key: props.get_property_as_string("key", None).into(),
-                                    open_once: props.get_property_as_bool("open_once", false),
-                                });
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-                _ => {}
+            if let Some(handler) = registry.get(&props.classname) {
+                handler(&mut commands, entity, props, &map_units);
             }
         }
     }
@@ -0,0 +1,555 @@
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use std::time::Duration;
+
+use crate::components::*;
+use crate::conversions::{to_bevy_direction, to_bevy_distance, to_bevy_position};
+use crate::environment::MapEnvironment;
+use crate::targets::UseTargets;
+use crate::PostBuildMapEvent;
+
+/// Sets up a map entity of a given classname, e.g. inserting a `PointLight` for `light`.
+/// Called once the map is built, with the entity, its properties and the map's units.
+pub type MapClassHandler =
+    Box<dyn Fn(&mut Commands, Entity, &MapEntityProperties, &MapUnits) + Send + Sync>;
+
+/// The handlers run for each classname when a map is built.
+/// Qevy registers handlers for the classes it knows about, see [`MapClassApp`] to add your own.
+#[derive(Resource, Default)]
+pub struct MapClassRegistry {
+    /// `None` for the classes that were disabled
+    handlers: HashMap<String, Option<MapClassHandler>>,
+    /// brush entities moved by the game, their colliders are kinematic so they push what they run into
+    kinematic: HashSet<String>,
+}
+
+impl MapClassRegistry {
+    /// Sets the handler of a classname, replacing the one it had
+    pub fn register(
+        &mut self,
+        classname: impl Into<String>,
+        handler: impl Fn(&mut Commands, Entity, &MapEntityProperties, &MapUnits) + Send + Sync + 'static,
+    ) {
+        self.handlers
+            .insert(classname.into(), Some(Box::new(handler)));
+    }
+
+    /// Sets the handler of a brush entity class moved by the game, like `func_door`.
+    /// Its colliders are kinematic instead of static
+    pub fn register_kinematic(
+        &mut self,
+        classname: impl Into<String>,
+        handler: impl Fn(&mut Commands, Entity, &MapEntityProperties, &MapUnits) + Send + Sync + 'static,
+    ) {
+        let classname = classname.into();
+        self.kinematic.insert(classname.clone());
+        self.register(classname, handler);
+    }
+
+    /// Removes the handler of a classname, the entities are still spawned with their `MapEntityProperties`
+    /// and static colliders
+    pub fn disable(&mut self, classname: &str) {
+        self.handlers.insert(classname.to_string(), None);
+        self.kinematic.remove(classname);
+    }
+
+    pub fn get(&self, classname: &str) -> Option<&MapClassHandler> {
+        self.handlers.get(classname)?.as_ref()
+    }
+
+    /// Whether the brush entities of the classname get kinematic colliders
+    pub fn is_kinematic(&self, classname: &str) -> bool {
+        self.kinematic.contains(classname)
+    }
+}
+
+/// ```ignore
+/// app.register_map_class("info_player_start", |commands, entity, props, _map_units| {
+///     commands.entity(entity).insert((SpawnPoint, props.transform));
+/// })
+/// .disable_map_class("light");
+/// ```
+pub trait MapClassApp {
+    /// Runs `handler` for every entity of the classname, replacing Qevy's handler if it has one
+    fn register_map_class(
+        &mut self,
+        classname: impl Into<String>,
+        handler: impl Fn(&mut Commands, Entity, &MapEntityProperties, &MapUnits) + Send + Sync + 'static,
+    ) -> &mut Self;
+
+    /// Like `register_map_class`, for brush entities moved by the game which get kinematic colliders
+    fn register_kinematic_map_class(
+        &mut self,
+        classname: impl Into<String>,
+        handler: impl Fn(&mut Commands, Entity, &MapEntityProperties, &MapUnits) + Send + Sync + 'static,
+    ) -> &mut Self;
+
+    /// Stops Qevy (or an earlier `register_map_class`) from handling the classname
+    fn disable_map_class(&mut self, classname: &str) -> &mut Self;
+}
+
+impl MapClassApp for App {
+    fn register_map_class(
+        &mut self,
+        classname: impl Into<String>,
+        handler: impl Fn(&mut Commands, Entity, &MapEntityProperties, &MapUnits) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.world_mut()
+            .get_resource_or_insert_with(MapClassRegistry::default)
+            .register(classname, handler);
+        self
+    }
+
+    fn register_kinematic_map_class(
+        &mut self,
+        classname: impl Into<String>,
+        handler: impl Fn(&mut Commands, Entity, &MapEntityProperties, &MapUnits) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.world_mut()
+            .get_resource_or_insert_with(MapClassRegistry::default)
+            .register_kinematic(classname, handler);
+        self
+    }
+
+    fn disable_map_class(&mut self, classname: &str) -> &mut Self {
+        self.world_mut()
+            .get_resource_or_insert_with(MapClassRegistry::default)
+            .disable(classname);
+        self
+    }
+}
+
+/// Registers the handlers for the classes Qevy knows about,
+/// leaving the ones the app already registered or disabled alone
+pub(crate) fn register_builtin_classes(registry: &mut MapClassRegistry) {
+    let mut builtins = MapClassRegistry::default();
+    register_qevy_classes(&mut builtins);
+    for (classname, handler) in builtins.handlers {
+        if registry.handlers.contains_key(&classname) {
+            continue;
+        }
+        if builtins.kinematic.contains(&classname) {
+            registry.kinematic.insert(classname.clone());
+        }
+        registry.handlers.insert(classname, handler);
+    }
+}
+
+fn register_qevy_classes(registry: &mut MapClassRegistry) {
+    registry.register("worldspawn", spawn_worldspawn);
+    registry.register("light", spawn_light);
+    registry.register("directional_light", spawn_directional_light);
+    registry.register_kinematic("mover", spawn_mover);
+    registry.register("trigger_once", spawn_trigger_once);
+    registry.register("trigger_multiple", spawn_trigger_multiple);
+    registry.register("trigger_relay", spawn_trigger_relay);
+    registry.register("trigger_counter", spawn_trigger_counter);
+    registry.register("logic_timer", spawn_logic_timer);
+    registry.register("multi_manager", spawn_multi_manager);
+    registry.register_kinematic("func_door", spawn_func_door);
+    registry.register_kinematic("func_door_rotating", spawn_func_door_rotating);
+    registry.register_kinematic("func_rotating", spawn_func_rotating);
+    registry.register_kinematic("func_train", spawn_func_train);
+    registry.register("path_corner", spawn_path_corner);
+    registry.register("func_water", spawn_func_water);
+}
+
+/// Runs the registered handler of each entity of a map that was just built
+pub fn map_class_system(
+    mut commands: Commands,
+    registry: Res<MapClassRegistry>,
+    map_units: Res<MapUnits>,
+    mut post_build_map_event: EventReader<PostBuildMapEvent>,
+    map_entities: Query<(Entity, &MapEntityProperties, &Parent)>,
+) {
+    for ev in post_build_map_event.read() {
+        for (entity, props, parent) in map_entities.iter() {
+            if parent.get() != ev.map {
+                continue;
+            }
+            if let Some(handler) = registry.get(&props.classname) {
+                handler(&mut commands, entity, props, &map_units);
+            }
+        }
+    }
+}
+
+pub fn spawn_worldspawn(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+    map_units: &MapUnits,
+) {
+    let environment = MapEnvironment::from_properties(props, map_units);
+    if !environment.is_empty() {
+        commands.entity(entity).insert(environment);
+    }
+}
+
+pub fn spawn_light(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+    _map_units: &MapUnits,
+) {
+    commands.entity(entity).insert((
+        props.transform,
+        PointLight {
+            color: props.get_property_as_color("color", Color::WHITE),
+            radius: props.get_property_as_f32("radius", 0.0),
+            range: props.get_property_as_f32("range", 10.0),
+            intensity: props.get_property_as_f32("intensity", 800.0),
+            shadows_enabled: props.get_property_as_bool("shadows_enabled", false),
+            ..default()
+        },
+    ));
+}
+
+pub fn spawn_directional_light(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+    _map_units: &MapUnits,
+) {
+    commands.entity(entity).insert((
+        props.transform,
+        DirectionalLight {
+            color: props.get_property_as_color("color", Color::WHITE),
+            illuminance: props.get_property_as_f32("illuminance", 10000.0),
+            shadows_enabled: props.get_property_as_bool("shadows_enabled", false),
+            ..default()
+        },
+    ));
+}
+
+pub fn spawn_mover(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+    map_units: &MapUnits,
+) {
+    let mut mover_entity = commands.entity(entity);
+    mover_entity.insert(Mover {
+        moving_time: Duration::from_secs_f32(props.get_property_as_f32("moving_time", 1.0)),
+        destination_time: Duration::from_secs_f32(
+            props.get_property_as_f32("destination_time", 2.0),
+        ),
+        destination_offset: {
+            to_bevy_position(
+                &props.get_property_as_vec3("destination_offset", Vec3::ZERO),
+                map_units,
+            )
+        },
+        state: MoverState::default(),
+    });
+
+    if let Some(mover_kind) = props.get_property_as_string("mover_kind", Some(&"linear".into())) {
+        if mover_kind == "door" {
+            mover_entity.insert(Door {
+                key: props.get_property_as_string("key", None),
+                open_once: props.get_property_as_bool("open_once", false),
+            });
+        }
+    }
+}
+
+pub fn spawn_trigger_once(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+    _map_units: &MapUnits,
+) {
+    commands.entity(entity).insert((
+        TriggerOnce {
+            target: props
+                .get_property_as_string("target", None)
+                .unwrap_or_default(),
+        },
+        UseTargets::from_properties(props),
+    ));
+}
+
+/// `wait` is the time between firing while something stays inside, -1 makes it fire only once like in Quake
+pub fn spawn_trigger_multiple(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+    _map_units: &MapUnits,
+) {
+    let target = props
+        .get_property_as_string("target", None)
+        .unwrap_or_default();
+    let wait = props.get_property_as_f32("wait", 0.0);
+    commands
+        .entity(entity)
+        .insert(UseTargets::from_properties(props));
+    if wait < 0.0 {
+        commands.entity(entity).insert(TriggerOnce { target });
+    } else {
+        commands.entity(entity).insert(TriggerMultiple {
+            target,
+            wait: (wait > 0.0).then(|| Duration::from_secs_f32(wait)),
+        });
+    }
+}
+
+pub fn spawn_trigger_relay(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+    _map_units: &MapUnits,
+) {
+    commands.entity(entity).insert((
+        TriggerRelay {
+            target: props
+                .get_property_as_string("target", None)
+                .unwrap_or_default(),
+        },
+        UseTargets::from_properties(props),
+    ));
+}
+
+/// `count` is the number of activations needed, 2 by default like in Quake
+pub fn spawn_trigger_counter(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+    _map_units: &MapUnits,
+) {
+    commands.entity(entity).insert((
+        TriggerCounter {
+            target: props
+                .get_property_as_string("target", None)
+                .unwrap_or_default(),
+            count: props.get_property_as_i32("count", 2).max(1) as u32,
+            activations: 0,
+        },
+        UseTargets::from_properties(props),
+    ));
+}
+
+/// `refire_time` and `random_jitter` are in seconds, `start_disabled` keeps it off until it's activated
+pub fn spawn_logic_timer(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+    _map_units: &MapUnits,
+) {
+    commands.entity(entity).insert((
+        LogicTimer::new(
+            props
+                .get_property_as_string("target", None)
+                .unwrap_or_default(),
+            Duration::from_secs_f32(props.get_property_as_f32("refire_time", 1.0).max(0.0)),
+            Duration::from_secs_f32(props.get_property_as_f32("random_jitter", 0.0).max(0.0)),
+            !props.get_property_as_bool("start_disabled", false),
+        ),
+        UseTargets::from_properties(props),
+    ));
+}
+
+/// Every other property is a targetname with its delay in seconds, `#` suffixes allow the same target more than once
+pub fn spawn_multi_manager(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+    _map_units: &MapUnits,
+) {
+    const RESERVED: &[&str] = &[
+        "classname",
+        "targetname",
+        "origin",
+        "angle",
+        "angles",
+        "spawnflags",
+    ];
+    let targets = props
+        .properties
+        .iter()
+        .filter(|(key, _)| !RESERVED.contains(&key.as_str()) && !key.starts_with('_'))
+        .filter_map(|(key, value)| {
+            let target = key.split('#').next().unwrap_or_default();
+            match value.trim().parse::<f32>() {
+                Ok(delay) => Some((target.to_string(), Duration::from_secs_f32(delay.max(0.0)))),
+                Err(_) => {
+                    warn!(
+                        "multi_manager: invalid delay for target `{}`: \"{}\"",
+                        target, value
+                    );
+                    None
+                }
+            }
+        })
+        .collect();
+    commands.entity(entity).insert(MultiManager {
+        targets,
+        pending: Vec::new(),
+    });
+}
+
+/// Quake's door keys: `angle`, `lip` (8), `speed` (100), `wait` (3, -1 stays open), `spawnflags` 1 (start open)
+/// and 32 (toggle), plus `team` to link doors together
+pub fn spawn_func_door(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+    map_units: &MapUnits,
+) {
+    commands.entity(entity).insert((
+        func_door(props, map_units),
+        UseTargets::from_properties(props),
+    ));
+}
+
+fn func_door(props: &MapEntityProperties, map_units: &MapUnits) -> FuncDoor {
+    const START_OPEN: i32 = 1;
+    const TOGGLE: i32 = 32;
+
+    let spawnflags = props.get_property_as_i32("spawnflags", 0);
+    let wait = props.get_property_as_f32("wait", 3.0);
+    FuncDoor {
+        move_dir: move_dir_from_angle(props.get_property_as_f32("angle", 0.0)),
+        lip: to_bevy_distance(props.get_property_as_f32("lip", 8.0), map_units),
+        speed: to_bevy_distance(props.get_property_as_f32("speed", 100.0), map_units),
+        wait: (wait >= 0.0).then(|| Duration::from_secs_f32(wait)),
+        toggle: spawnflags & TOGGLE != 0,
+        start_open: spawnflags & START_OPEN != 0,
+        team: props
+            .get_property_as_string("team", None)
+            .filter(|team| !team.is_empty()),
+        target: props
+            .get_property_as_string("target", None)
+            .unwrap_or_default(),
+    }
+}
+
+/// A `func_door` turning by `distance` degrees (90) at `speed` degrees per second (100), like in Half-Life.
+/// `spawnflags` 2 turns it the other way, 64 and 128 turn it about the X and Y axes instead of Z
+pub fn spawn_func_door_rotating(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+    map_units: &MapUnits,
+) {
+    const REVERSE: i32 = 2;
+    const X_AXIS: i32 = 64;
+    const Y_AXIS: i32 = 128;
+
+    let spawnflags = props.get_property_as_i32("spawnflags", 0);
+    let mut distance = props.get_property_as_f32("distance", 90.0).to_radians();
+    if spawnflags & REVERSE != 0 {
+        distance = -distance;
+    }
+    commands.entity(entity).insert((
+        // it doesn't slide, so it only needs the door's state
+        FuncDoor {
+            move_dir: Vec3::ZERO,
+            lip: 0.0,
+            speed: props.get_property_as_f32("speed", 100.0).to_radians(),
+            ..func_door(props, map_units)
+        },
+        DoorRotation {
+            axis: rotation_axis(spawnflags, X_AXIS, Y_AXIS),
+            closed: 0.0,
+            open: distance,
+            angle: 0.0,
+        },
+        UseTargets::from_properties(props),
+    ));
+}
+
+/// Spins at `speed` degrees per second (100), `spawnflags` 1 starts it on and 2 turns it the other way.
+/// 4 and 8 spin it about the X and Y axes instead of Z
+pub fn spawn_func_rotating(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+    _map_units: &MapUnits,
+) {
+    const START_ON: i32 = 1;
+    const REVERSE: i32 = 2;
+    const X_AXIS: i32 = 4;
+    const Y_AXIS: i32 = 8;
+
+    let spawnflags = props.get_property_as_i32("spawnflags", 0);
+    let speed = props.get_property_as_f32("speed", 100.0).to_radians();
+    commands.entity(entity).insert(FuncRotating {
+        axis: rotation_axis(spawnflags, X_AXIS, Y_AXIS),
+        speed: if spawnflags & REVERSE != 0 {
+            -speed
+        } else {
+            speed
+        },
+        enabled: spawnflags & START_ON != 0,
+    });
+}
+
+/// The map's Z axis unless one of the axis flags is set
+fn rotation_axis(spawnflags: i32, x_axis: i32, y_axis: i32) -> Vec3 {
+    let map_axis = if spawnflags & x_axis != 0 {
+        Vec3::X
+    } else if spawnflags & y_axis != 0 {
+        Vec3::Y
+    } else {
+        Vec3::Z
+    };
+    to_bevy_direction(&map_axis)
+}
+
+/// The direction of Quake's `angle` key: a yaw in degrees, -1 for up and -2 for down
+pub fn move_dir_from_angle(angle: f32) -> Vec3 {
+    let map_dir = if angle == -1.0 {
+        Vec3::Z
+    } else if angle == -2.0 {
+        Vec3::NEG_Z
+    } else {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        Vec3::new(cos, sin, 0.0)
+    };
+    to_bevy_direction(&map_dir)
+}
+
+/// `target` is the first `path_corner`, `speed` is in map units per second (100)
+pub fn spawn_func_train(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+    map_units: &MapUnits,
+) {
+    commands.entity(entity).insert(FuncTrain {
+        first_corner: props
+            .get_property_as_string("target", None)
+            .unwrap_or_default(),
+        speed: to_bevy_distance(props.get_property_as_f32("speed", 100.0), map_units),
+    });
+}
+
+/// Corners are linked into the `PathGraph` when the map is built, they get a transform to be found in the world
+pub fn spawn_path_corner(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+    _map_units: &MapUnits,
+) {
+    commands.entity(entity).insert(props.transform);
+}
+
+/// `liquid` is `water` (default), `slime` or `lava`, `density` is compared to water
+/// and `current` is in map units per second (`x y z`)
+pub fn spawn_func_water(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+    map_units: &MapUnits,
+) {
+    let kind = props
+        .get_property_as_string("liquid", None)
+        .and_then(|liquid| LiquidKind::from_name(&liquid))
+        .unwrap_or_default();
+    let current = props.get_property_as_vec3("current", Vec3::ZERO);
+    commands.entity(entity).insert(LiquidVolume {
+        kind,
+        density: props.get_property_as_f32("density", kind.density()),
+        current: to_bevy_position(&current, map_units),
+    });
+}
@@ -1,3 +1,4 @@
+use bevy::ecs::entity::EntityHashSet;
 use bevy::prelude::*;
 use std::{collections::BTreeMap, time::Duration};
 
@@ -7,14 +8,24 @@ pub struct Map {
     pub asset: Handle<crate::MapAsset>,
 }
 
-#[derive(Default, Bundle)]
-#[deprecated(
-    note = "As of Bevy 0.15, bundles are deprecated in favor of required components. Use just `Map` instead, along with non-default values for `Transform` and `Visibility` if needed."
-)]
-pub struct MapBundle {
-    pub map: Map,
-    pub transform: TransformBundle,
-    pub visibility: VisibilityBundle,
+#[allow(deprecated)]
+pub use map_bundle::MapBundle;
+
+// the derived impls use the deprecated bundles too, so they're kept out of the way of the lint
+#[allow(deprecated)]
+mod map_bundle {
+    use super::Map;
+    use bevy::prelude::*;
+
+    #[derive(Default, Bundle)]
+    #[deprecated(
+        note = "As of Bevy 0.15, bundles are deprecated in favor of required components. Use just `Map` instead, along with non-default values for `Transform` and `Visibility` if needed."
+    )]
+    pub struct MapBundle {
+        pub map: Map,
+        pub transform: TransformBundle,
+        pub visibility: VisibilityBundle,
+    }
 }
 
 #[derive(Default, Component)]
@@ -55,11 +66,7 @@ impl MapEntityProperties {
     pub fn get_property_as_bool(&self, key: &str, default: bool) -> bool {
         if let Some(value) = self.properties.get(key) {
             if let Ok(value) = value.parse::<i32>() {
-                if value == 1 {
-                    return true;
-                } else {
-                    return false;
-                }
+                return value == 1;
             }
         }
         default
@@ -105,28 +112,212 @@ pub struct BrushEntity;
 #[derive(Default, Component)]
 pub struct Brush;
 
+/// The bounds of all the brushes of a brush entity, relative to the entity
+#[derive(Component, Debug, Clone, Copy)]
+pub struct BrushBounds {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl BrushBounds {
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) / 2.0
+    }
+}
+
+/// The point a brush entity turns about, relative to the entity.
+/// Entities with an `origin` key or a brush textured `origin` are placed on that point, so it's zero for them
+#[derive(Component, Debug, Clone, Copy)]
+pub struct BrushPivot(pub Vec3);
+
+/// A brush collider with sky faces (see `textures::TextureBehavior::sky`), Quake removes what flies into the sky
+#[derive(Component, Debug, Clone, Copy)]
+pub struct SkyPortal;
+
 #[derive(Component)]
 pub struct TriggeredOnce;
 
+/// Sent when a trigger fires, its target is then resolved into `targets::Activated` events
 #[derive(Event)]
 pub struct TriggeredEvent {
+    /// the trigger that fired
+    pub trigger: Entity,
     pub target: String,
     pub triggered_by: Entity,
 }
 
-#[derive(Default, Component)]
+/// Sent when an instigator starts overlapping a trigger
+#[derive(Event, Debug)]
+pub struct TriggerEntered {
+    pub trigger: Entity,
+    pub instigator: Entity,
+}
+
+/// Sent when an instigator stops overlapping a trigger, or is despawned while inside
+#[derive(Event, Debug)]
+pub struct TriggerExited {
+    pub trigger: Entity,
+    pub instigator: Entity,
+}
+
+#[derive(Default, Component, Clone)]
+#[require(TriggerOccupancy)]
 pub struct TriggerOnce {
     pub target: String,
 }
 
-#[derive(Default, Component)]
+#[derive(Default, Component, Clone)]
+#[require(TriggerOccupancy)]
 pub struct TriggerMultiple {
     pub target: String,
+    /// Fires again after this long while an instigator stays inside, from the entity's `wait` property.
+    /// Without it, it fires each time an instigator enters
+    pub wait: Option<Duration>,
+}
+
+/// The instigators inside a trigger, kept up to date by the trigger systems
+#[derive(Default, Component)]
+pub struct TriggerOccupancy {
+    pub occupants: EntityHashSet,
+    /// counts down the `wait` of a `TriggerMultiple` after it fired
+    pub(crate) cooldown: Option<Timer>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum LiquidKind {
+    #[default]
+    Water,
+    Slime,
+    Lava,
+}
+
+impl LiquidKind {
+    /// How dense the liquid is compared to water, used when the map doesn't say
+    pub fn density(&self) -> f32 {
+        match self {
+            Self::Water => 1.0,
+            Self::Slime => 1.5,
+            Self::Lava => 3.0,
+        }
+    }
+
+    /// `water`, `slime` or `lava`, ignoring case
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "water" => Some(Self::Water),
+            "slime" => Some(Self::Slime),
+            "lava" => Some(Self::Lava),
+            _ => None,
+        }
+    }
+}
+
+/// A volume of liquid, either a `func_water` or a brush with a liquid texture (`*water`, `*slime`, `*lava`).
+/// Its colliders are sensors, `TriggerInstigator`s going in and out of it send `LiquidEntered`, `LiquidDepth` and `LiquidExited`
+#[derive(Component, Debug, Clone, Copy)]
+#[require(TriggerOccupancy, Transform, Visibility)]
+pub struct LiquidVolume {
+    pub kind: LiquidKind,
+    /// compared to water
+    pub density: f32,
+    /// in bevy units per second, for the game to push what's inside along
+    pub current: Vec3,
+}
+
+impl LiquidVolume {
+    pub fn new(kind: LiquidKind) -> Self {
+        Self {
+            kind,
+            density: kind.density(),
+            current: Vec3::ZERO,
+        }
+    }
+}
+
+#[derive(Event, Debug)]
+pub struct LiquidEntered {
+    pub liquid: Entity,
+    pub instigator: Entity,
+}
+
+#[derive(Event, Debug)]
+pub struct LiquidExited {
+    pub liquid: Entity,
+    pub instigator: Entity,
+}
+
+/// Sent when how deep an instigator is in a liquid changes, from the liquid's surface down to the instigator's origin
+#[derive(Event, Debug)]
+pub struct LiquidDepth {
+    pub liquid: Entity,
+    pub instigator: Entity,
+    /// in bevy units
+    pub depth: f32,
+}
+
+/// Fires its target when activated, mostly to give a trigger's targets their own `delay`, `killtarget` or `message`
+#[derive(Default, Component, Clone)]
+pub struct TriggerRelay {
+    pub target: String,
+}
+
+/// Fires its target once it has been activated `count` times, then stops
+#[derive(Default, Component, Clone)]
+pub struct TriggerCounter {
+    pub target: String,
+    pub count: u32,
+    pub activations: u32,
+}
+
+/// Fires its target every `refire_time`, give or take up to `jitter` at random each time.
+/// Activating it turns it on or off
+#[derive(Component, Clone)]
+pub struct LogicTimer {
+    pub target: String,
+    pub refire_time: Duration,
+    pub jitter: Duration,
+    pub enabled: bool,
+    pub(crate) timer: Timer,
+}
+
+impl LogicTimer {
+    pub fn new(target: String, refire_time: Duration, jitter: Duration, enabled: bool) -> Self {
+        let mut logic_timer = Self {
+            target,
+            refire_time,
+            jitter,
+            enabled,
+            timer: Timer::default(),
+        };
+        logic_timer.restart();
+        logic_timer
+    }
+
+    /// Starts waiting for the next time to fire
+    pub fn restart(&mut self) {
+        let jitter = self.jitter.as_secs_f32() * (fastrand::f32() * 2.0 - 1.0);
+        let wait = (self.refire_time.as_secs_f32() + jitter).max(0.0);
+        self.timer = Timer::new(Duration::from_secs_f32(wait), TimerMode::Once);
+    }
+}
+
+/// Fires each of its targets after their own delay when activated, like Half-Life's `multi_manager`
+#[derive(Default, Component, Clone)]
+pub struct MultiManager {
+    pub targets: Vec<(String, Duration)>,
+    /// the targets still waiting for their delay, with the activator
+    pub(crate) pending: Vec<(Timer, String, Entity)>,
 }
 
 #[derive(Default, Component)]
 pub struct TriggerTarget {
     pub target_name: String,
+    /// the `Map` it was spawned from, only the triggers of that map fire it
+    pub map: Option<Entity>,
 }
 
 #[derive(Default, Component)]
@@ -156,9 +347,9 @@ impl MoverState {
     pub fn get_fraction(&self) -> f32 {
         match self {
             Self::AtStart => 0.0,
-            Self::MovingToDestination(timer) => timer.fraction().min(1.0).max(0.0),
-            Self::MovingToStart(timer) => timer.fraction().min(1.0).max(0.0),
-            Self::AtDestination(timer) => timer.fraction().min(1.0).max(0.0),
+            Self::MovingToDestination(timer) => timer.fraction().clamp(0.0, 1.0),
+            Self::MovingToStart(timer) => timer.fraction().clamp(0.0, 1.0),
+            Self::AtDestination(timer) => timer.fraction().clamp(0.0, 1.0),
         }
     }
 }
@@ -171,17 +362,129 @@ pub struct Door {
     pub open_once: bool,
 }
 
+/// A Quake `func_door`, sliding along `move_dir` by the size of its brushes minus `lip` when activated.
+/// Doors without a targetname open when a `TriggerInstigator` comes close
+#[derive(Component, Clone)]
+#[require(DoorState)]
+pub struct FuncDoor {
+    /// the direction it opens towards, from `angle` (-1 is up, -2 down)
+    pub move_dir: Vec3,
+    /// how much of the door is left sticking out when open, in bevy units
+    pub lip: f32,
+    /// in bevy units per second, radians per second for a `func_door_rotating`
+    pub speed: f32,
+    /// how long it stays open, `None` (-1) never closes
+    pub wait: Option<Duration>,
+    /// opens and closes each time it's activated instead of closing on its own (`spawnflags` 32)
+    pub toggle: bool,
+    /// spawns open and closes when activated (`spawnflags` 1)
+    pub start_open: bool,
+    /// doors with the same team open and close together
+    pub team: Option<String>,
+    /// fired when it starts opening
+    pub target: String,
+}
+
+#[derive(Component, Default, Debug, Clone, PartialEq)]
+pub enum DoorState {
+    #[default]
+    Closed,
+    Opening,
+    /// counting down the door's `wait`, or staying open
+    Open(Option<Timer>),
+    Closing,
+}
+
+/// What a door's `Transform` is when closed and open, set once its `BrushBounds` are known
+#[derive(Component, Debug, Clone, Copy)]
+pub struct DoorTravel {
+    pub closed: Transform,
+    pub open: Transform,
+}
+
+/// Turns a `FuncDoor` about its `BrushPivot` instead of sliding it, for `func_door_rotating`.
+/// Angles are in radians, from where the door was built
+#[derive(Component, Debug, Clone, Copy)]
+pub struct DoorRotation {
+    pub axis: Vec3,
+    pub closed: f32,
+    /// `distance`, swapped with `closed` for the doors that start open
+    pub open: f32,
+    /// how far it's turned now, measured from its `Transform` while it moves
+    pub angle: f32,
+}
+
+/// A brush spinning about its `BrushPivot`, activating it turns it on or off
+#[derive(Component, Debug, Clone, Copy)]
+pub struct FuncRotating {
+    pub axis: Vec3,
+    /// in radians per second, negative turns the other way
+    pub speed: f32,
+    pub enabled: bool,
+}
+
+/// The trigger around a door without a targetname, opening it when entered
+#[derive(Component, Clone, Copy)]
+#[require(TriggerOccupancy, Transform, Visibility)]
+pub struct DoorTouchTrigger {
+    pub door: Entity,
+}
+
+/// A Quake `func_train`, moving along a path of `path_corner`s starting at `first_corner`.
+/// Trains with a targetname wait to be activated, activating a train stops or starts it
+#[derive(Component, Clone)]
+pub struct FuncTrain {
+    /// the targetname of the corner it starts at, from `target`
+    pub first_corner: String,
+    /// in bevy units per second
+    pub speed: f32,
+}
+
+/// Where a `FuncTrain` is on its path, each holding the corner it goes to next.
+/// Set once the train is placed on its first corner
+#[derive(Component, Debug, Clone, PartialEq)]
+pub enum TrainState {
+    /// waiting to be activated
+    Stopped(Entity),
+    /// waiting out the `wait` of the corner it's at
+    Waiting(Entity, Timer),
+    Moving(Entity),
+}
+
 /// The units used in the map
 /// Bevy units are the default units used in Bevy, which are 1 unit = 1 meter
 /// Trenchbroom units are the units used in Trenchbroom, which are 16 units = 1 foot
-#[derive(Resource, Clone)]
+#[derive(Resource, Clone, Default)]
 pub enum MapUnits {
+    #[default]
     Bevy,
     Trenchbroom,
 }
 
-impl Default for MapUnits {
-    fn default() -> Self {
-        Self::Bevy
+/// How the brushes of an entity become colliders, set for every entity on the `MapAssetLoaderPlugin`
+/// and for one entity with its `_collider` key (`convex`, `compound`, `trimesh` or `none`)
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColliderShape {
+    /// a convex hull collider per brush
+    #[default]
+    Convex,
+    /// a single collider made of the convex hulls of all the brushes
+    Compound,
+    /// a single collider made of the faces of all the brushes, hollow so it doesn't suit triggers and liquids
+    Trimesh,
+    /// no colliders at all
+    None,
+}
+
+impl ColliderShape {
+    /// `convex`, `compound`, `trimesh` or `none`, ignoring case
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "convex" => Some(Self::Convex),
+            "compound" => Some(Self::Compound),
+            "trimesh" => Some(Self::Trimesh),
+            "none" => Some(Self::None),
+            _ => None,
+        }
     }
 }
@@ -1,5 +1,5 @@
 use bevy::prelude::*;
-use shambler::{Vector2, Vector3};
+use shambler::Vector3;
 
 use crate::components::MapUnits;
 
@@ -20,6 +20,19 @@ pub fn to_bevy_position(vector: &Vec3, map_units: &MapUnits) -> Vec3 {
     }
 }
 
+/// An axis or direction in map space, only the axes are swapped since it has no length in map units
+pub fn to_bevy_direction(vector: &Vec3) -> Vec3 {
+    Vec3::new(vector.y, vector.z, vector.x)
+}
+
+/// A length in map units, like a speed or a door's `lip`
+pub fn to_bevy_distance(distance: f32, map_units: &MapUnits) -> f32 {
+    match map_units {
+        MapUnits::Bevy => distance * SHAMBLER_UNITS_TO_BEVY_METERS,
+        MapUnits::Trenchbroom => distance,
+    }
+}
+
 pub fn to_bevy_rotation(rotation: &Vec3) -> Quat {
     Quat::from_euler(
         bevy::math::EulerRot::YXZ,
@@ -48,11 +61,3 @@ pub fn to_bevy_vec3s(normals: &Vec<Vector3>) -> Vec<Vec3> {
     }
     bevy_normals
 }
-
-pub fn uvs_to_bevy_vec2s(uvs: &Vec<Vector2>) -> Vec<Vec2> {
-    let mut bevy_uvs: Vec<Vec2> = Vec::new();
-    for uv in uvs {
-        bevy_uvs.push(Vec2::new(uv.x, uv.y));
-    }
-    bevy_uvs
-}
@@ -0,0 +1,190 @@
+use shambler::brush::BrushId;
+use shambler::face::{FaceId, FacePlanes, FaceVertices};
+use shambler::{BrushFaces, Vector3};
+use std::collections::BTreeSet;
+
+/// How far (in map units) a vertex can be outside of a brush and still count as inside
+const CULL_EPSILON: f32 = 0.01;
+
+/// Finds the faces of `brushes` that can never be seen because they are inside one of the `occluders`,
+/// e.g. the bottom of a wall standing on a floor or two brushes sharing a side.
+/// Faces lying on an occluder's side and facing the same way are kept, since that side is visible.
+/// Only covering by a single brush is detected, a face hidden by several brushes together is kept.
+pub fn hidden_faces(
+    brushes: &[BrushId],
+    occluders: &[BrushId],
+    brush_faces: &BrushFaces,
+    face_vertices: &FaceVertices,
+    face_planes: &FacePlanes,
+) -> BTreeSet<FaceId> {
+    let occluders = occluders
+        .iter()
+        .filter_map(|brush_id| {
+            let faces = brush_faces.get(brush_id)?;
+            let bounds = bounds(
+                faces
+                    .iter()
+                    .filter_map(|face_id| face_vertices.get(face_id))
+                    .flatten(),
+            )?;
+            Some((*brush_id, faces, bounds))
+        })
+        .collect::<Vec<_>>();
+
+    let mut hidden = BTreeSet::new();
+    for brush_id in brushes {
+        let Some(faces) = brush_faces.get(brush_id) else {
+            continue;
+        };
+
+        for face_id in faces {
+            let (Some(vertices), Some(plane)) =
+                (face_vertices.get(face_id), face_planes.get(face_id))
+            else {
+                continue;
+            };
+            let Some((min, max)) = bounds(vertices.iter()) else {
+                continue;
+            };
+
+            let covered = occluders
+                .iter()
+                .filter(|(occluder_id, _, (occluder_min, occluder_max))| {
+                    occluder_id != brush_id
+                        && (0..3).all(|i| {
+                            min[i] >= occluder_min[i] - CULL_EPSILON
+                                && max[i] <= occluder_max[i] + CULL_EPSILON
+                        })
+                })
+                .any(|(_, occluder_faces, _)| {
+                    occluder_faces.iter().all(|occluder_face_id| {
+                        let Some(occluder_plane) = face_planes.get(occluder_face_id) else {
+                            return true;
+                        };
+                        let n = occluder_plane.normal();
+                        let d = occluder_plane.distance();
+                        let inside = vertices
+                            .iter()
+                            .all(|vertex| n.dot(vertex) - d <= CULL_EPSILON);
+                        let on_side = vertices
+                            .iter()
+                            .all(|vertex| (n.dot(vertex) - d).abs() <= CULL_EPSILON);
+                        // on this side and facing out with it, so the face is what you see there
+                        let same_facing = on_side && n.dot(plane.normal()) > 0.0;
+                        inside && !same_facing
+                    })
+                });
+
+            if covered {
+                hidden.insert(*face_id);
+            }
+        }
+    }
+    hidden
+}
+
+fn bounds<'a>(vertices: impl Iterator<Item = &'a Vector3>) -> Option<(Vector3, Vector3)> {
+    vertices.fold(None, |bounds, vertex| match bounds {
+        None => Some((*vertex, *vertex)),
+        Some((min, max)) => Some((min.inf(vertex), max.sup(vertex))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shambler::Plane3d;
+
+    /// Axis aligned boxes from their `(min, max)` corners, as brushes with six faces each
+    fn boxes(
+        corners: &[([f32; 3], [f32; 3])],
+    ) -> (Vec<BrushId>, BrushFaces, FaceVertices, FacePlanes) {
+        let mut brush_faces = Vec::new();
+        let mut face_vertices = Vec::new();
+        let mut face_planes = Vec::new();
+        for (brush, (min, max)) in corners.iter().enumerate() {
+            let (min, max) = (Vector3::from(*min), Vector3::from(*max));
+            let mut faces = Vec::new();
+            for axis in 0..3 {
+                let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+                for (sign, side) in [(-1.0, min[axis]), (1.0, max[axis])] {
+                    let corner = |a: f32, b: f32| {
+                        let mut vertex = Vector3::zeros();
+                        vertex[axis] = side;
+                        vertex[u] = a;
+                        vertex[v] = b;
+                        vertex
+                    };
+                    let mut normal = Vector3::zeros();
+                    normal[axis] = sign;
+                    let face_id = FaceId(face_vertices.len());
+                    faces.push(face_id);
+                    face_vertices.push((
+                        face_id,
+                        vec![
+                            corner(min[u], min[v]),
+                            corner(max[u], min[v]),
+                            corner(max[u], max[v]),
+                            corner(min[u], max[v]),
+                        ],
+                    ));
+                    face_planes.push((
+                        face_id,
+                        Plane3d {
+                            n: normal,
+                            d: sign * side,
+                        },
+                    ));
+                }
+            }
+            brush_faces.push((BrushId(brush), faces));
+        }
+        (
+            (0..corners.len()).map(BrushId).collect(),
+            brush_faces.into_iter().collect(),
+            face_vertices.into_iter().collect(),
+            face_planes.into_iter().collect(),
+        )
+    }
+
+    /// The faces of a wall standing on a 4x4 floor, the wall going from `wall_min` to `wall_max`
+    fn hidden_wall_faces(wall_min: [f32; 3], wall_max: [f32; 3]) -> BTreeSet<FaceId> {
+        let (brushes, brush_faces, face_vertices, face_planes) =
+            boxes(&[([0.0, 0.0, -1.0], [4.0, 4.0, 0.0]), (wall_min, wall_max)]);
+        hidden_faces(
+            &brushes,
+            &brushes,
+            &brush_faces,
+            &face_vertices,
+            &face_planes,
+        )
+    }
+
+    // faces go -x, +x, -y, +y, -z, +z for each box
+    const FLOOR_TOP: FaceId = FaceId(5);
+    const WALL_BOTTOM: FaceId = FaceId(10);
+    const WALL_TOP: FaceId = FaceId(11);
+
+    #[test]
+    fn hides_faces_inside_another_brush() {
+        let hidden = hidden_wall_faces([1.0, 1.0, 0.0], [2.0, 2.0, 3.0]);
+        assert_eq!(hidden, BTreeSet::from([WALL_BOTTOM]));
+    }
+
+    #[test]
+    fn keeps_faces_facing_out_of_the_side_they_share() {
+        // sunk into the floor, the block's top is flush with the floor's and seen along with it
+        let hidden = hidden_wall_faces([1.0, 1.0, -1.0], [2.0, 2.0, 0.0]);
+        assert!(!hidden.contains(&WALL_TOP));
+        assert!(!hidden.contains(&FLOOR_TOP));
+        // while its sides are inside the floor
+        assert!(hidden.contains(&FaceId(6)));
+    }
+
+    #[test]
+    fn keeps_partly_covered_faces() {
+        // hanging over the edge of the floor, part of the wall's bottom can be seen from below
+        let hidden = hidden_wall_faces([3.0, 1.0, 0.0], [5.0, 2.0, 3.0]);
+        assert!(hidden.is_empty());
+    }
+}
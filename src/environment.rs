@@ -0,0 +1,279 @@
+use bevy::core_pipeline::Skybox;
+use bevy::pbr::{DistanceFog, FogFalloff};
+use bevy::prelude::*;
+use bevy::render::render_resource::{TextureViewDescriptor, TextureViewDimension};
+
+use crate::components::{MapEntityProperties, MapUnits};
+use crate::conversions::{to_bevy_direction, to_bevy_distance};
+
+/// The sky, lighting and fog of a map, read from its worldspawn's properties:
+///
+/// - `sky` or `skyname`: a cubemap image in the assets folder, either a `ktx2`/`dds` cubemap
+///   or six square faces stacked vertically in the order +X, -X, +Y, -Y, +Z, -Z, and its `sky_brightness`
+/// - `_ambient` and `_ambient_color`: the brightness and color of the `AmbientLight`
+/// - `_sunlight`, `_sunlight_color` and `_sun_mangle`: the illuminance in lux, color
+///   and "yaw pitch roll" direction (straight down by default) of the sun's `DirectionalLight`
+/// - `fog`: Quake's "density r g b" exponential fog, or `fog_start`, `fog_end` and `fog_color` for linear fog
+///
+/// Colors are either between 0 and 1 or between 0 and 255.
+/// The sky and fog are put on every `Camera3d`.
+#[derive(Component, Debug, Clone, Default)]
+pub struct MapEnvironment {
+    pub sky: Option<String>,
+    pub sky_brightness: f32,
+    pub ambient: Option<AmbientLight>,
+    pub sun: Option<Sun>,
+    pub fog: Option<DistanceFog>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Sun {
+    pub light: DirectionalLight,
+    /// the direction the light travels in
+    pub direction: Vec3,
+}
+
+/// Marks the `DirectionalLight` spawned under the worldspawn for its sun
+#[derive(Component, Debug, Clone, Copy)]
+pub struct MapSun;
+
+/// The cubemap of a `MapEnvironment`'s sky, put on cameras once it is loaded
+#[derive(Component, Debug, Clone)]
+pub struct MapSky {
+    pub image: Handle<Image>,
+    pub loaded: bool,
+}
+
+impl MapEnvironment {
+    pub fn from_properties(props: &MapEntityProperties, map_units: &MapUnits) -> Self {
+        let sky = props
+            .get_property_as_string("sky", None)
+            .or_else(|| props.get_property_as_string("skyname", None))
+            .filter(|sky| !sky.trim().is_empty());
+
+        let ambient = props.properties.get("_ambient").map(|_| AmbientLight {
+            color: property_color(props, "_ambient_color").unwrap_or(Color::WHITE),
+            brightness: props.get_property_as_f32("_ambient", 0.0),
+        });
+
+        let sun = props.properties.get("_sunlight").map(|_| {
+            let mangle = props.get_property_as_vec3("_sun_mangle", Vec3::new(0.0, -90.0, 0.0));
+            let (yaw, pitch) = (mangle.x.to_radians(), mangle.y.to_radians());
+            let direction = Vec3::new(
+                pitch.cos() * yaw.cos(),
+                pitch.cos() * yaw.sin(),
+                pitch.sin(),
+            );
+            Sun {
+                light: DirectionalLight {
+                    color: property_color(props, "_sunlight_color").unwrap_or(Color::WHITE),
+                    illuminance: props.get_property_as_f32("_sunlight", 0.0),
+                    ..default()
+                },
+                direction: to_bevy_direction(&direction).normalize(),
+            }
+        });
+
+        let fog = fog(props, map_units);
+
+        Self {
+            sky,
+            sky_brightness: props.get_property_as_f32("sky_brightness", 1000.0),
+            ambient,
+            sun,
+            fog,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sky.is_none() && self.ambient.is_none() && self.sun.is_none() && self.fog.is_none()
+    }
+}
+
+fn fog(props: &MapEntityProperties, map_units: &MapUnits) -> Option<DistanceFog> {
+    if let Some(fog) = props.properties.get("fog") {
+        let values = fog
+            .split_whitespace()
+            .map(|value| value.parse::<f32>().ok())
+            .collect::<Option<Vec<f32>>>()?;
+        let density = *values.first()?;
+        let color = match values[1..] {
+            [r, g, b] => Color::srgb(r, g, b),
+            _ => Color::srgb(0.3, 0.3, 0.3),
+        };
+        // same curve as Quake engines, which use a 64th of the density per unit
+        return Some(DistanceFog {
+            color,
+            falloff: FogFalloff::ExponentialSquared {
+                density: density / to_bevy_distance(64.0, map_units),
+            },
+            ..default()
+        });
+    }
+    let end = props.properties.get("fog_end")?.parse::<f32>().ok()?;
+    Some(DistanceFog {
+        color: property_color(props, "fog_color").unwrap_or(Color::srgb(0.3, 0.3, 0.3)),
+        falloff: FogFalloff::Linear {
+            start: to_bevy_distance(props.get_property_as_f32("fog_start", 0.0), map_units),
+            end: to_bevy_distance(end, map_units),
+        },
+        ..default()
+    })
+}
+
+/// A color property either between 0 and 1, or between 0 and 255 like ericw-tools' light colors
+fn property_color(props: &MapEntityProperties, key: &str) -> Option<Color> {
+    let color = props.get_property_as_vec3(key, Vec3::NAN);
+    if color.is_nan() {
+        return None;
+    }
+    let color = if color.max_element() > 1.0 {
+        color / 255.0
+    } else {
+        color
+    };
+    Some(Color::srgb(color.x, color.y, color.z))
+}
+
+/// Sets the ambient light and spawns the sun of new map environments under their worldspawn.
+/// Removing an environment takes its sun away, and the `AmbientLight` it replaced comes back
+/// once no environment sets one anymore.
+pub fn environment_setup_system(
+    mut commands: Commands,
+    ambient_light: Option<Res<AmbientLight>>,
+    mut replaced_ambient: Local<Option<Option<AmbientLight>>>,
+    mut removed: RemovedComponents<MapEnvironment>,
+    environments: Query<(Entity, Ref<MapEnvironment>)>,
+    suns: Query<(Entity, &Parent), With<MapSun>>,
+) {
+    let removed = removed.read().collect::<Vec<_>>();
+    for (sun, _) in suns
+        .iter()
+        .filter(|(_, parent)| removed.contains(&parent.get()))
+    {
+        commands.entity(sun).despawn_recursive();
+    }
+    // rebuilt maps despawn their worldspawn and spawn a new one in the same frame, which keeps the light it replaced
+    if !removed.is_empty()
+        && !environments
+            .iter()
+            .any(|(_, environment)| environment.ambient.is_some())
+    {
+        match replaced_ambient.take() {
+            Some(Some(ambient)) => commands.insert_resource(ambient),
+            Some(None) => commands.remove_resource::<AmbientLight>(),
+            None => {}
+        }
+    }
+
+    for (entity, environment) in environments.iter() {
+        if !environment.is_changed() {
+            continue;
+        }
+        if let Some(ambient) = &environment.ambient {
+            replaced_ambient.get_or_insert_with(|| ambient_light.as_deref().cloned());
+            commands.insert_resource(ambient.clone());
+        }
+
+        // a replaced environment takes its sun with it
+        for (sun, _) in suns.iter().filter(|(_, parent)| parent.get() == entity) {
+            commands.entity(sun).despawn_recursive();
+        }
+        if let Some(sun) = &environment.sun {
+            let up = if sun.direction.abs().y > 0.99 {
+                Vec3::Z
+            } else {
+                Vec3::Y
+            };
+            commands.entity(entity).with_child((
+                MapSun,
+                sun.light.clone(),
+                Transform::default().looking_to(sun.direction, up),
+            ));
+        }
+    }
+}
+
+/// Puts the fog of map environments on cameras, when either of them is added
+pub fn camera_fog_system(
+    mut commands: Commands,
+    environments: Query<Ref<MapEnvironment>>,
+    cameras: Query<(Entity, Ref<Camera3d>)>,
+) {
+    for environment in environments.iter() {
+        let Some(fog) = &environment.fog else {
+            continue;
+        };
+        for (camera, camera3d) in cameras.iter() {
+            if environment.is_added() || camera3d.is_added() {
+                commands.entity(camera).insert(fog.clone());
+            }
+        }
+    }
+}
+
+/// Loads the skies of map environments, turning stacked images into cubemaps, and puts them on cameras
+pub fn sky_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    mut image_events: EventReader<AssetEvent<Image>>,
+    environments: Query<(Entity, &MapEnvironment), Added<MapEnvironment>>,
+    mut skies: Query<(&mut MapSky, &MapEnvironment)>,
+    cameras: Query<(Entity, Ref<Camera3d>)>,
+) {
+    for (entity, environment) in environments.iter() {
+        if let Some(sky) = &environment.sky {
+            commands.entity(entity).insert(MapSky {
+                image: asset_server.load(sky.clone()),
+                loaded: false,
+            });
+        }
+    }
+
+    for event in image_events.read() {
+        let AssetEvent::LoadedWithDependencies { id } = event else {
+            continue;
+        };
+        for (mut sky, _) in skies.iter_mut().filter(|(sky, _)| sky.image.id() == *id) {
+            sky.loaded = images.get_mut(*id).is_none_or(as_cubemap);
+        }
+    }
+    // images shared with an earlier sky were already loaded, so there's no event for them
+    for (mut sky, _) in skies.iter_mut() {
+        if sky.is_added() && !sky.loaded && asset_server.is_loaded_with_dependencies(&sky.image) {
+            sky.loaded = images.get_mut(&sky.image).is_none_or(as_cubemap);
+        }
+    }
+
+    for (sky, environment) in skies.iter_mut() {
+        if !sky.loaded {
+            continue;
+        }
+        for (camera, camera3d) in cameras.iter() {
+            if sky.is_changed() || camera3d.is_added() {
+                commands.entity(camera).insert(Skybox {
+                    image: sky.image.clone(),
+                    brightness: environment.sky_brightness,
+                    ..default()
+                });
+            }
+        }
+    }
+}
+
+/// Turns six square faces stacked vertically into a cubemap, returning whether the image can be a sky
+fn as_cubemap(image: &mut Image) -> bool {
+    if image.texture_descriptor.array_layer_count() == 1 {
+        if image.height() != image.width() * 6 {
+            warn!("sky image is not six square faces stacked vertically");
+            return false;
+        }
+        image.reinterpret_stacked_2d_as_array(6);
+        image.texture_view_descriptor = Some(TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::Cube),
+            ..default()
+        });
+    }
+    true
+}
@@ -1,110 +1,842 @@
+use crate::build::trigger_box_collider;
 use crate::components::*;
+use crate::conversions::to_bevy_distance;
+use crate::io::FireOutput;
+use crate::paths::PathGraph;
+use crate::targets::Activated;
+#[cfg(any(feature = "rapier", feature = "avian"))]
+use bevy::ecs::entity::{EntityHashMap, EntityHashSet};
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
+use bevy::utils::HashMap;
+use std::f32::consts::{PI, TAU};
+#[cfg(any(feature = "rapier", feature = "avian"))]
+use std::time::Duration;
 
 #[cfg(feature = "rapier")]
 use bevy_rapier3d::prelude::*;
 
+/// The events sent by the trigger systems
+#[derive(SystemParam)]
+pub struct TriggerEvents<'w> {
+    entered: EventWriter<'w, TriggerEntered>,
+    exited: EventWriter<'w, TriggerExited>,
+    triggered: EventWriter<'w, TriggeredEvent>,
+}
+
+/// The triggers the trigger systems look after
+#[cfg(any(feature = "rapier", feature = "avian"))]
+type TriggerData = (
+    Entity,
+    Option<&'static TriggerOnce>,
+    Option<&'static TriggerMultiple>,
+    Has<TriggeredOnce>,
+    &'static mut TriggerOccupancy,
+);
+
+#[cfg(any(feature = "rapier", feature = "avian"))]
+type TriggerItem<'a> = (
+    Entity,
+    Option<&'a TriggerOnce>,
+    Option<&'a TriggerMultiple>,
+    bool,
+    Mut<'a, TriggerOccupancy>,
+);
+
+/// Compares the instigators overlapping a trigger with the ones that were inside,
+/// sending the enter/exit events and firing the trigger. Shared by both physics backends
+/// so they behave the same, they only need to find what overlaps each trigger.
+#[cfg(any(feature = "rapier", feature = "avian"))]
+fn update_trigger(
+    commands: &mut Commands,
+    events: &mut TriggerEvents,
+    delta: Duration,
+    (trigger_entity, once, multiple, triggered_once, mut occupancy): TriggerItem,
+    overlapping: EntityHashSet,
+) {
+    let mut entered = overlapping
+        .difference(&occupancy.occupants)
+        .copied()
+        .collect::<Vec<_>>();
+    let mut exited = occupancy
+        .occupants
+        .difference(&overlapping)
+        .copied()
+        .collect::<Vec<_>>();
+    // sorted so events go out in the same order every run
+    entered.sort();
+    exited.sort();
+
+    for instigator in exited.iter() {
+        events.exited.send(TriggerExited {
+            trigger: trigger_entity,
+            instigator: *instigator,
+        });
+    }
+    for instigator in entered.iter() {
+        events.entered.send(TriggerEntered {
+            trigger: trigger_entity,
+            instigator: *instigator,
+        });
+    }
+    occupancy.occupants = overlapping;
+
+    if let Some(trigger) = once {
+        if let (false, Some(instigator)) = (triggered_once, entered.first()) {
+            events.triggered.send(TriggeredEvent {
+                trigger: trigger_entity,
+                target: trigger.target.clone(),
+                triggered_by: *instigator,
+            });
+            commands.entity(trigger_entity).insert(TriggeredOnce);
+        }
+    } else if let Some(trigger) = multiple {
+        if let Some(cooldown) = occupancy.cooldown.as_mut() {
+            cooldown.tick(delta);
+            if cooldown.finished() {
+                occupancy.cooldown = None;
+            }
+        }
+        if occupancy.cooldown.is_some() {
+            return;
+        }
+
+        let instigators = match trigger.wait {
+            // fires for everyone entering
+            None => entered,
+            // fires once per `wait`, for whoever is inside
+            Some(_) => entered
+                .first()
+                .copied()
+                .or_else(|| occupancy.occupants.iter().min().copied())
+                .into_iter()
+                .collect(),
+        };
+        for instigator in instigators.iter() {
+            events.triggered.send(TriggeredEvent {
+                trigger: trigger_entity,
+                target: trigger.target.clone(),
+                triggered_by: *instigator,
+            });
+        }
+        if !instigators.is_empty() {
+            occupancy.cooldown = trigger.wait.map(|wait| Timer::new(wait, TimerMode::Once));
+        }
+    }
+}
+
+/// Sends `TriggerEntered`/`TriggerExited` as `TriggerInstigator`s move in and out of triggers,
+/// and `TriggeredEvent` when a trigger fires
 #[cfg(feature = "rapier")]
 pub fn rapier_trigger_system(
     rapier_context: ReadDefaultRapierContext,
+    time: Res<Time>,
     mut commands: Commands,
-    trigger_once: Query<(Entity, &TriggerOnce), Without<TriggeredOnce>>,
-    trigger_multiple: Query<(Entity, &TriggerMultiple)>,
+    trigger_colliders: Query<(Entity, &Parent), With<bevy_rapier3d::prelude::Collider>>,
+    mut triggers: Query<TriggerData>,
     trigger_instigators: Query<Entity, (With<TriggerInstigator>,)>,
-    mut trigger_events: EventWriter<TriggeredEvent>,
-) {
-    for instigator_entity in trigger_instigators.iter() {
-        for (trigger_entity, trigger) in trigger_multiple.iter() {
-            if rapier_context.intersection_pair(instigator_entity, trigger_entity) == Some(true) {
-                trigger_events.send(TriggeredEvent {
-                    target: trigger.target.clone(),
-                    triggered_by: instigator_entity,
-                });
-            }
+    mut events: TriggerEvents,
+) {
+    // a trigger is a brush entity, with a collider for each of its brushes
+    let mut overlapping = EntityHashMap::<EntityHashSet>::default();
+    for (collider_entity, parent) in trigger_colliders.iter() {
+        if !triggers.contains(parent.get()) {
+            continue;
         }
-
-        for (trigger_entity, trigger) in trigger_once.iter() {
-            if rapier_context.intersection_pair(instigator_entity, trigger_entity) == Some(true) {
-                trigger_events.send(TriggeredEvent {
-                    target: trigger.target.clone(),
-                    triggered_by: instigator_entity,
-                });
-                commands.entity(trigger_entity).insert(TriggeredOnce);
+        let occupants = overlapping.entry(parent.get()).or_default();
+        for instigator_entity in trigger_instigators.iter() {
+            if rapier_context.intersection_pair(instigator_entity, collider_entity) == Some(true) {
+                occupants.insert(instigator_entity);
             }
         }
     }
-}
 
-#[cfg(feature = "avian")]
-use avian3d::prelude::*;
+    for trigger in triggers.iter_mut() {
+        let occupants = overlapping.remove(&trigger.0).unwrap_or_default();
+        update_trigger(&mut commands, &mut events, time.delta(), trigger, occupants);
+    }
+}
 
+/// Sends `TriggerEntered`/`TriggerExited` as `TriggerInstigator`s move in and out of triggers,
+/// and `TriggeredEvent` when a trigger fires.
+/// Trigger colliders are sensors keeping track of what touches them in `CollidingEntities`,
+/// which avian fills in from its collisions.
 #[cfg(feature = "avian")]
 pub fn avian_trigger_system(
-    spatial_query: SpatialQuery,
+    time: Res<Time>,
     mut commands: Commands,
-    map_entity: Query<Entity, With<Map>>,
-    trigger_once: Query<
-        (
-            Entity,
-            &TriggerOnce,
-            &GlobalTransform,
-            &Transform,
-            &avian3d::prelude::Collider,
-        ),
-        Without<TriggeredOnce>,
+    trigger_colliders: Query<
+        (&Parent, &avian3d::prelude::CollidingEntities),
+        With<avian3d::prelude::Sensor>,
     >,
-    trigger_multiple: Query<(
-        Entity,
-        &TriggerMultiple,
-        &GlobalTransform,
-        &Transform,
-        &avian3d::prelude::Collider,
-    )>,
+    mut triggers: Query<TriggerData>,
     trigger_instigators: Query<Entity, With<TriggerInstigator>>,
-    mut trigger_events: EventWriter<TriggeredEvent>,
+    mut events: TriggerEvents,
 ) {
-    let map_entity = map_entity.get_single();
+    // a trigger is a brush entity, with a collider for each of its brushes
+    let mut overlapping = EntityHashMap::<EntityHashSet>::default();
+    for (parent, colliding_entities) in trigger_colliders.iter() {
+        if !triggers.contains(parent.get()) {
+            continue;
+        }
+        overlapping.entry(parent.get()).or_default().extend(
+            colliding_entities
+                .iter()
+                .filter(|entity| trigger_instigators.contains(**entity)),
+        );
+    }
 
-    if let Ok(map_entity) = map_entity {
-        for instigator_entity in trigger_instigators.iter() {
-            for (trigger_entity, trigger, gtransform, transform, collider) in
-                trigger_multiple.iter()
-            {
-                let intersections = spatial_query.shape_intersections(
-                    collider,
-                    gtransform.translation(),
-                    transform.rotation,
-                    &SpatialQueryFilter::from_excluded_entities([map_entity]),
-                );
-
-                for entity in intersections.iter() {
-                    if *entity == instigator_entity {
-                        trigger_events.send(TriggeredEvent {
-                            target: trigger.target.clone(),
-                            triggered_by: instigator_entity,
-                        });
-                        commands.entity(trigger_entity).insert(TriggeredOnce);
+    for trigger in triggers.iter_mut() {
+        let occupants = overlapping.remove(&trigger.0).unwrap_or_default();
+        update_trigger(&mut commands, &mut events, time.delta(), trigger, occupants);
+    }
+}
+
+/// Fires the targets of `TriggerRelay`s, `TriggerCounter`s and `MultiManager`s when they're activated,
+/// and turns `LogicTimer`s on and off
+pub fn logic_activation_system(
+    mut activated_events: EventReader<Activated>,
+    relays: Query<&TriggerRelay>,
+    mut counters: Query<&mut TriggerCounter>,
+    mut timers: Query<&mut LogicTimer>,
+    mut managers: Query<&mut MultiManager>,
+    mut triggered: EventWriter<TriggeredEvent>,
+) {
+    for event in activated_events.read() {
+        let entity = event.target_entity;
+        if let Ok(relay) = relays.get(entity) {
+            triggered.send(TriggeredEvent {
+                trigger: entity,
+                target: relay.target.clone(),
+                triggered_by: event.activator,
+            });
+        }
+        if let Ok(mut counter) = counters.get_mut(entity) {
+            if counter.activations < counter.count {
+                counter.activations += 1;
+                if counter.activations == counter.count {
+                    triggered.send(TriggeredEvent {
+                        trigger: entity,
+                        target: counter.target.clone(),
+                        triggered_by: event.activator,
+                    });
+                }
+            }
+        }
+        if let Ok(mut timer) = timers.get_mut(entity) {
+            timer.enabled = !timer.enabled;
+            if timer.enabled {
+                timer.restart();
+            }
+        }
+        if let Ok(mut manager) = managers.get_mut(entity) {
+            let pending = manager
+                .targets
+                .iter()
+                .map(|(target, delay)| {
+                    (
+                        Timer::new(*delay, TimerMode::Once),
+                        target.clone(),
+                        event.activator,
+                    )
+                })
+                .collect::<Vec<_>>();
+            manager.pending.extend(pending);
+        }
+    }
+}
+
+/// Fires `LogicTimer`s and the delayed targets of `MultiManager`s
+pub fn logic_timer_system(
+    time: Res<Time>,
+    mut timers: Query<(Entity, &mut LogicTimer)>,
+    mut managers: Query<(Entity, &mut MultiManager)>,
+    mut triggered: EventWriter<TriggeredEvent>,
+) {
+    for (entity, mut timer) in timers.iter_mut() {
+        if !timer.enabled {
+            continue;
+        }
+        timer.timer.tick(time.delta());
+        if timer.timer.finished() {
+            triggered.send(TriggeredEvent {
+                trigger: entity,
+                target: timer.target.clone(),
+                triggered_by: entity,
+            });
+            timer.restart();
+        }
+    }
+
+    for (entity, mut manager) in managers.iter_mut() {
+        for (timer, _, _) in manager.pending.iter_mut() {
+            timer.tick(time.delta());
+        }
+        let (finished, waiting) = std::mem::take(&mut manager.pending)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(timer, _, _)| timer.finished());
+        manager.pending = waiting;
+        for (_, target, activator) in finished {
+            triggered.send(TriggeredEvent {
+                trigger: entity,
+                target,
+                triggered_by: activator,
+            });
+        }
+    }
+}
+
+type DoorSetupData = (
+    Entity,
+    &'static FuncDoor,
+    &'static BrushBounds,
+    &'static mut Transform,
+    Option<&'static mut DoorRotation>,
+    Option<&'static BrushPivot>,
+    Option<&'static Parent>,
+    Has<TriggerTarget>,
+);
+
+/// Works out where `FuncDoor`s open to once their bounds are known, moving the ones that start open,
+/// and spawns a `DoorTouchTrigger` around the doors without a targetname
+pub fn door_setup_system(
+    mut commands: Commands,
+    map_units: Res<MapUnits>,
+    mut doors: Query<DoorSetupData, Without<DoorTravel>>,
+) {
+    for (entity, door, bounds, mut transform, rotation, pivot, parent, named) in doors.iter_mut() {
+        let travel = match rotation {
+            // rotating doors turn instead of sliding
+            Some(mut rotation) => {
+                // like in Quake both ends are swapped for the doors that start open, so activating them closes them
+                if door.start_open {
+                    let DoorRotation { closed, open, .. } = *rotation;
+                    (rotation.closed, rotation.open) = (open, closed);
+                }
+                rotation.angle = rotation.closed;
+                let built = *transform;
+                let pivot = brush_pivot(pivot, bounds);
+                DoorTravel {
+                    closed: turned(built, pivot, rotation.axis, rotation.closed),
+                    open: turned(built, pivot, rotation.axis, rotation.open),
+                }
+            }
+            None => {
+                let distance = (door.move_dir.abs().dot(bounds.size()) - door.lip).max(0.0);
+                let open =
+                    transform.with_translation(transform.translation + door.move_dir * distance);
+                if door.start_open {
+                    DoorTravel {
+                        closed: open,
+                        open: *transform,
+                    }
+                } else {
+                    DoorTravel {
+                        closed: *transform,
+                        open,
+                    }
+                }
+            }
+        };
+        *transform = travel.closed;
+        commands.entity(entity).insert(travel);
+
+        if !named {
+            // Quake opens doors from 60 units away, horizontally
+            let margin = to_bevy_distance(60.0, &map_units);
+            let half_extents = bounds.size() / 2.0 + Vec3::new(margin, 0.0, margin);
+            let trigger = commands
+                .spawn((
+                    DoorTouchTrigger { door: entity },
+                    Transform::from_translation(transform.translation + bounds.center()),
+                ))
+                .with_child((trigger_box_collider(half_extents), Transform::default()))
+                .id();
+            if let Some(parent) = parent {
+                commands.entity(parent.get()).add_child(trigger);
+            }
+        }
+    }
+}
+
+/// Opens or closes `FuncDoor`s along with their team when they're activated or their touch trigger is entered,
+/// and keeps them open while someone is inside their touch trigger
+pub fn door_activation_system(
+    mut activated_events: EventReader<Activated>,
+    mut entered_events: EventReader<TriggerEntered>,
+    touch_triggers: Query<(&DoorTouchTrigger, &TriggerOccupancy)>,
+    mut doors: Query<(Entity, &FuncDoor, &mut DoorState, Option<&Parent>)>,
+    mut triggered: EventWriter<TriggeredEvent>,
+) {
+    let used = activated_events
+        .read()
+        .map(|event| (event.target_entity, event.activator))
+        .chain(entered_events.read().filter_map(|event| {
+            let (touch_trigger, _) = touch_triggers.get(event.trigger).ok()?;
+            Some((touch_trigger.door, event.instigator))
+        }))
+        .collect::<Vec<_>>();
+
+    for (door_entity, activator) in used {
+        let Ok((_, door, state, _)) = doors.get(door_entity) else {
+            continue;
+        };
+        // like Quake, toggle doors close when used while opening or open, others only wait longer
+        let open = match *state {
+            DoorState::Closed | DoorState::Closing => true,
+            DoorState::Opening | DoorState::Open(_) if door.toggle => false,
+            DoorState::Opening => continue,
+            DoorState::Open(_) => {
+                for_door_team(&mut doors, door_entity, |_, _, mut state| {
+                    if let DoorState::Open(Some(timer)) = state.as_mut() {
+                        timer.reset();
                     }
+                });
+                continue;
+            }
+        };
+
+        for_door_team(&mut doors, door_entity, |entity, door, mut state| {
+            match (open, state.as_ref()) {
+                (true, DoorState::Closed | DoorState::Closing) => {
+                    *state = DoorState::Opening;
+                    triggered.send(TriggeredEvent {
+                        trigger: entity,
+                        target: door.target.clone(),
+                        triggered_by: activator,
+                    });
                 }
+                (false, DoorState::Opening | DoorState::Open(_)) => *state = DoorState::Closing,
+                _ => {}
+            }
+        });
+    }
+
+    for (touch_trigger, occupancy) in touch_triggers.iter() {
+        if occupancy.occupants.is_empty() {
+            continue;
+        }
+        for_door_team(&mut doors, touch_trigger.door, |_, door, mut state| {
+            if let (false, DoorState::Open(Some(timer))) = (door.toggle, state.as_mut()) {
+                timer.reset();
             }
+        });
+    }
+}
+
+/// Runs `f` for a door and the doors of its team in the same map
+fn for_door_team(
+    doors: &mut Query<(Entity, &FuncDoor, &mut DoorState, Option<&Parent>)>,
+    door_entity: Entity,
+    mut f: impl FnMut(Entity, &FuncDoor, Mut<DoorState>),
+) {
+    let Ok((_, door, _, parent)) = doors.get(door_entity) else {
+        return;
+    };
+    let team = door.team.clone();
+    let parent = parent.map(Parent::get);
+
+    for (entity, door, state, door_parent) in doors.iter_mut() {
+        let in_team = team.is_some() && door.team == team && door_parent.map(Parent::get) == parent;
+        if entity == door_entity || in_team {
+            f(entity, door, state);
+        }
+    }
+}
+
+type DoorMoveData = (
+    Entity,
+    &'static FuncDoor,
+    &'static mut DoorState,
+    &'static DoorTravel,
+    &'static BrushBounds,
+    &'static mut Transform,
+    Option<KinematicVelocity>,
+    Option<&'static mut DoorRotation>,
+    Option<&'static BrushPivot>,
+);
 
-            for (trigger_entity, trigger, gtransform, transform, collider) in trigger_once.iter() {
-                let intersections = spatial_query.shape_intersections(
-                    collider,
-                    gtransform.translation(),
-                    transform.rotation,
-                    &SpatialQueryFilter::default(),
-                );
-
-                for entity in intersections.iter() {
-                    if *entity == instigator_entity {
-                        trigger_events.send(TriggeredEvent {
-                            target: trigger.target.clone(),
-                            triggered_by: instigator_entity,
-                        });
-                        commands.entity(trigger_entity).insert(TriggeredOnce);
+/// Moves `FuncDoor`s between their closed and open positions,
+/// firing their `OnFullyOpen` and `OnFullyClosed` outputs when they get there.
+/// Each step starts from where the door is, and it's put right on its end once it gets there
+pub fn door_move_system(
+    time: Res<Time>,
+    mut doors: Query<DoorMoveData>,
+    mut fire_output: EventWriter<FireOutput>,
+) {
+    let delta = time.delta();
+    for (entity, door, mut state, travel, bounds, mut transform, velocity, mut rotation, pivot) in
+        doors.iter_mut()
+    {
+        let step = door.speed * delta.as_secs_f32();
+        let mut move_door = |transform: &Transform, open: bool| {
+            let end = if open { travel.open } else { travel.closed };
+            match rotation.as_mut() {
+                // rotating doors turn instead of sliding
+                Some(rotation) => {
+                    rotation.angle = door_angle(transform, travel, rotation);
+                    let end_angle = if open { rotation.open } else { rotation.closed };
+                    let to_angle = end_angle - rotation.angle;
+                    let turn = to_angle.clamp(-step, step);
+                    if turn == to_angle {
+                        // where it's settled next
+                        rotation.angle = end_angle;
+                        return (end, true);
                     }
+                    let angle = rotation.angle + turn - rotation.closed;
+                    let pivot = brush_pivot(pivot, bounds);
+                    (turned(travel.closed, pivot, rotation.axis, angle), false)
+                }
+                None => {
+                    let mut target = *transform;
+                    let arrived = move_towards(&mut target.translation, end.translation, step);
+                    (target, arrived)
+                }
+            }
+        };
+        match state.as_mut() {
+            DoorState::Closed => settle_kinematic(&mut transform, velocity, travel.closed),
+            DoorState::Open(timer) => {
+                if timer
+                    .as_mut()
+                    .is_some_and(|timer| timer.tick(delta).finished())
+                {
+                    *state = DoorState::Closing;
+                }
+                settle_kinematic(&mut transform, velocity, travel.open);
+            }
+            DoorState::Opening => {
+                let (target, arrived) = move_door(&transform, true);
+                if arrived {
+                    let wait = door.wait.filter(|_| !door.toggle);
+                    *state = DoorState::Open(wait.map(|wait| Timer::new(wait, TimerMode::Once)));
+                    fire_output.send(FireOutput {
+                        entity,
+                        output: "OnFullyOpen".into(),
+                        activator: entity,
+                    });
+                }
+                move_kinematic(&mut transform, velocity, target, delta.as_secs_f32());
+            }
+            DoorState::Closing => {
+                let (target, arrived) = move_door(&transform, false);
+                if arrived {
+                    *state = DoorState::Closed;
+                    fire_output.send(FireOutput {
+                        entity,
+                        output: "OnFullyClosed".into(),
+                        activator: entity,
+                    });
+                }
+                move_kinematic(&mut transform, velocity, target, delta.as_secs_f32());
+            }
+        }
+    }
+}
+
+/// How far a rotating door is turned, measured from its transform so it follows what the physics did.
+/// Of the angles giving that rotation, the one nearest to the last is picked so doors can turn past half a turn
+fn door_angle(transform: &Transform, travel: &DoorTravel, rotation: &DoorRotation) -> f32 {
+    let turn = transform.rotation * travel.closed.rotation.inverse();
+    let measured = rotation.closed + 2.0 * turn.xyz().dot(rotation.axis).atan2(turn.w);
+    rotation.angle + (measured - rotation.angle + PI).rem_euclid(TAU) - PI
+}
+
+/// `transform` turned by `angle` radians about `axis` and `pivot`
+fn turned(mut transform: Transform, pivot: Vec3, axis: Vec3, angle: f32) -> Transform {
+    rotate_about(&mut transform, pivot, Quat::from_axis_angle(axis, angle));
+    transform
+}
+
+/// Turns `transform` by `rotation` about `pivot`, a point relative to the transform that stays in place
+fn rotate_about(transform: &mut Transform, pivot: Vec3, rotation: Quat) {
+    let world_pivot = transform.transform_point(pivot);
+    transform.rotation = (rotation * transform.rotation).normalize();
+    transform.translation = world_pivot - transform.rotation * (transform.scale * pivot);
+}
+
+/// Brush entities without a `BrushPivot` turn about their center
+fn brush_pivot(pivot: Option<&BrushPivot>, bounds: &BrushBounds) -> Vec3 {
+    pivot.map_or_else(|| bounds.center(), |pivot| pivot.0)
+}
+
+type RotatingData = (
+    &'static mut FuncRotating,
+    &'static BrushBounds,
+    Option<&'static BrushPivot>,
+    &'static mut Transform,
+    Option<KinematicVelocity>,
+);
+
+/// Spins the enabled `FuncRotating`s, activating one turns it on or off.
+/// Each step turns it by `speed` times the step, so on avian's fixed step it spins at `speed` whatever the frame rate
+pub fn rotating_system(
+    time: Res<Time>,
+    mut activated_events: EventReader<Activated>,
+    mut rotating: Query<RotatingData>,
+) {
+    for event in activated_events.read() {
+        if let Ok((mut rotating, ..)) = rotating.get_mut(event.target_entity) {
+            rotating.enabled = !rotating.enabled;
+        }
+    }
+
+    for (rotating, bounds, pivot, mut transform, velocity) in rotating.iter_mut() {
+        let mut target = *transform;
+        if rotating.enabled {
+            let angle = rotating.speed * time.delta_secs();
+            rotate_about(
+                &mut target,
+                brush_pivot(pivot, bounds),
+                Quat::from_axis_angle(rotating.axis, angle),
+            );
+        }
+        move_kinematic(&mut transform, velocity, target, time.delta_secs());
+    }
+}
+
+/// Moves `position` up to `step` closer to `target`, true once it's there
+fn move_towards(position: &mut Vec3, target: Vec3, step: f32) -> bool {
+    let to_target = target - *position;
+    if to_target.length() <= step {
+        *position = target;
+        true
+    } else {
+        *position += to_target.normalize() * step;
+        false
+    }
+}
+
+/// The velocities of the kinematic body of brush entities moved by the game
+#[cfg(feature = "avian")]
+type KinematicVelocity = (
+    &'static mut avian3d::prelude::LinearVelocity,
+    &'static mut avian3d::prelude::AngularVelocity,
+);
+#[cfg(not(feature = "avian"))]
+type KinematicVelocity = ();
+
+/// Takes a kinematic brush entity to `target` by the next step. With avian it gets the velocities that
+/// take it there, so it pushes and carries what it runs into, otherwise its transform is set right away.
+/// Avian moves bodies on its fixed timestep, so movers are run on it too and `delta` is the physics step
+#[cfg(feature = "avian")]
+fn move_kinematic(
+    transform: &mut Mut<Transform>,
+    velocity: Option<(
+        Mut<avian3d::prelude::LinearVelocity>,
+        Mut<avian3d::prelude::AngularVelocity>,
+    )>,
+    target: Transform,
+    delta: f32,
+) {
+    match velocity {
+        Some((mut linear, mut angular)) if delta > 0.0 => {
+            linear.0 = (target.translation - transform.translation) / delta;
+            let mut turn = target.rotation * transform.rotation.inverse();
+            // the short way around
+            if turn.w < 0.0 {
+                turn = -turn;
+            }
+            angular.0 = turn.to_scaled_axis() / delta;
+        }
+        _ => **transform = target,
+    }
+}
+
+#[cfg(not(feature = "avian"))]
+fn move_kinematic(
+    transform: &mut Mut<Transform>,
+    velocity: Option<()>,
+    target: Transform,
+    _delta: f32,
+) {
+    settle_kinematic(transform, velocity, target);
+}
+
+/// Stops a kinematic brush entity right on `pose`, so whatever the physics step left over doesn't add up
+#[cfg(feature = "avian")]
+fn settle_kinematic(
+    transform: &mut Mut<Transform>,
+    velocity: Option<(
+        Mut<avian3d::prelude::LinearVelocity>,
+        Mut<avian3d::prelude::AngularVelocity>,
+    )>,
+    pose: Transform,
+) {
+    if let Some((mut linear, mut angular)) = velocity {
+        linear.set_if_neq(avian3d::prelude::LinearVelocity(Vec3::ZERO));
+        angular.set_if_neq(avian3d::prelude::AngularVelocity(Vec3::ZERO));
+    }
+    transform.set_if_neq(pose);
+}
+
+#[cfg(not(feature = "avian"))]
+fn settle_kinematic(transform: &mut Mut<Transform>, _velocity: Option<()>, pose: Transform) {
+    transform.set_if_neq(pose);
+}
+
+type TrainSetupData = (
+    Entity,
+    &'static FuncTrain,
+    &'static BrushBounds,
+    &'static Parent,
+    &'static mut Transform,
+    Has<TriggerTarget>,
+);
+
+/// Puts `FuncTrain`s on their first corner, the ones without a targetname start right away
+pub fn train_setup_system(
+    mut commands: Commands,
+    paths: Res<PathGraph>,
+    mut trains: Query<TrainSetupData, Without<TrainState>>,
+) {
+    for (entity, train, bounds, parent, mut transform, named) in trains.iter_mut() {
+        let Some(first) = paths.find(parent.get(), &train.first_corner) else {
+            continue;
+        };
+        let corner = paths.get(first).unwrap();
+        // like in Quake, the corner is where the train's lowest corner ends up
+        transform.translation = corner.position - bounds.min;
+        let next = corner.next.unwrap_or(first);
+        let state = match corner.wait {
+            _ if named => TrainState::Stopped(next),
+            None => TrainState::Stopped(next),
+            Some(wait) if wait.is_zero() => TrainState::Moving(next),
+            Some(wait) => TrainState::Waiting(next, Timer::new(wait, TimerMode::Once)),
+        };
+        commands.entity(entity).insert(state);
+    }
+}
+
+/// Starts stopped `FuncTrain`s when they're activated, and stops the others
+pub fn train_activation_system(
+    mut activated_events: EventReader<Activated>,
+    mut trains: Query<&mut TrainState>,
+) {
+    for event in activated_events.read() {
+        let Ok(mut state) = trains.get_mut(event.target_entity) else {
+            continue;
+        };
+        *state = match *state {
+            TrainState::Stopped(next) => TrainState::Moving(next),
+            TrainState::Waiting(next, _) | TrainState::Moving(next) => TrainState::Stopped(next),
+        };
+    }
+}
+
+type TrainMoveData = (
+    Entity,
+    &'static FuncTrain,
+    &'static BrushBounds,
+    &'static mut TrainState,
+    &'static mut Transform,
+    Option<KinematicVelocity>,
+);
+
+/// Moves `FuncTrain`s from corner to corner, waiting at each for their `wait`.
+/// Sends `TriggeredEvent` for the corner's `pathtarget` and fires its `OnPass` output when a train gets there.
+/// Each step heads for the corner from where the train is, so what the physics left over doesn't add up along the path
+pub fn train_move_system(
+    time: Res<Time>,
+    paths: Res<PathGraph>,
+    mut trains: Query<TrainMoveData>,
+    mut triggered: EventWriter<TriggeredEvent>,
+    mut fire_output: EventWriter<FireOutput>,
+) {
+    let delta = time.delta();
+    for (entity, train, bounds, mut state, mut transform, velocity) in trains.iter_mut() {
+        let mut target = *transform;
+        let arrived = match state.as_mut() {
+            TrainState::Stopped(_) => None,
+            TrainState::Waiting(next, timer) => {
+                if timer.tick(delta).finished() {
+                    *state = TrainState::Moving(*next);
                 }
+                None
+            }
+            TrainState::Moving(next) => paths.get(*next).and_then(|corner| {
+                let step = train.speed * delta.as_secs_f32();
+                move_towards(&mut target.translation, corner.position - bounds.min, step)
+                    .then_some((*next, corner))
+            }),
+        };
+        move_kinematic(&mut transform, velocity, target, delta.as_secs_f32());
+
+        if let Some((corner_entity, corner)) = arrived {
+            if let Some(pathtarget) = corner.pathtarget.as_ref() {
+                triggered.send(TriggeredEvent {
+                    trigger: corner_entity,
+                    target: pathtarget.clone(),
+                    triggered_by: entity,
+                });
+            }
+            fire_output.send(FireOutput {
+                entity: corner_entity,
+                output: "OnPass".into(),
+                activator: entity,
+            });
+
+            let next = corner.next.unwrap_or(corner_entity);
+            *state = match corner.wait {
+                // the end of a path that doesn't loop
+                _ if corner.next.is_none() => TrainState::Stopped(next),
+                None => TrainState::Stopped(next),
+                Some(wait) if wait.is_zero() => TrainState::Moving(next),
+                Some(wait) => TrainState::Waiting(next, Timer::new(wait, TimerMode::Once)),
+            };
+        }
+    }
+}
+
+/// The events sent by the liquid system
+#[derive(SystemParam)]
+pub struct LiquidEvents<'w> {
+    entered: EventWriter<'w, LiquidEntered>,
+    exited: EventWriter<'w, LiquidExited>,
+    depth: EventWriter<'w, LiquidDepth>,
+}
+
+/// Sends `LiquidEntered` and `LiquidExited` as `TriggerInstigator`s go in and out of `LiquidVolume`s,
+/// and `LiquidDepth` while they're inside
+pub fn liquid_system(
+    mut entered_events: EventReader<TriggerEntered>,
+    mut exited_events: EventReader<TriggerExited>,
+    liquids: Query<(Entity, &BrushBounds, &GlobalTransform, &TriggerOccupancy), With<LiquidVolume>>,
+    instigators: Query<&GlobalTransform, With<TriggerInstigator>>,
+    mut events: LiquidEvents,
+    mut depths: Local<HashMap<(Entity, Entity), f32>>,
+) {
+    for event in exited_events.read() {
+        if liquids.contains(event.trigger) {
+            depths.remove(&(event.trigger, event.instigator));
+            events.exited.send(LiquidExited {
+                liquid: event.trigger,
+                instigator: event.instigator,
+            });
+        }
+    }
+    for event in entered_events.read() {
+        if liquids.contains(event.trigger) {
+            events.entered.send(LiquidEntered {
+                liquid: event.trigger,
+                instigator: event.instigator,
+            });
+        }
+    }
+
+    for (liquid, bounds, transform, occupancy) in liquids.iter() {
+        let surface = transform.transform_point(bounds.max).y;
+        for instigator in occupancy.occupants.iter() {
+            let Ok(instigator_transform) = instigators.get(*instigator) else {
+                continue;
+            };
+            let depth = surface - instigator_transform.translation().y;
+            if depths.insert((liquid, *instigator), depth) != Some(depth) {
+                events.depth.send(LiquidDepth {
+                    liquid,
+                    instigator: *instigator,
+                    depth,
+                });
             }
         }
     }
@@ -0,0 +1,345 @@
+use bevy::prelude::*;
+use std::time::Duration;
+
+use crate::components::*;
+use crate::targets::{MapOf, TargetNames};
+
+/// One connection of an output, written `target,input,parameter,delay,times_to_fire` in the map like in Source.
+/// Only the target and input are required, fields can also be separated by `\x1b` like in Source's vmf.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Connection {
+    /// a targetname, `!self` for the entity firing the output or `!activator`
+    pub target: String,
+    pub input: String,
+    pub parameter: Option<String>,
+    pub delay: Duration,
+    /// how many more times the connection can fire, `None` for no limit (`-1`)
+    pub times_to_fire: Option<u32>,
+}
+
+impl Connection {
+    pub fn parse(value: &str) -> Option<Self> {
+        let separator = if value.contains('\x1b') { '\x1b' } else { ',' };
+        let mut fields = value.split(separator).map(str::trim);
+        let target = fields.next().filter(|target| !target.is_empty())?;
+        let input = fields.next().filter(|input| !input.is_empty())?;
+        let parameter = fields.next().filter(|parameter| !parameter.is_empty());
+        let delay = match fields.next().filter(|delay| !delay.is_empty()) {
+            Some(delay) => Duration::try_from_secs_f32(delay.parse().ok()?).ok()?,
+            None => Duration::ZERO,
+        };
+        let times_to_fire = match fields.next().filter(|times| !times.is_empty()) {
+            Some(times) => match times.parse::<i32>().ok()? {
+                times if times < 0 => None,
+                times => Some(times as u32),
+            },
+            None => None,
+        };
+        if fields.next().is_some() {
+            return None;
+        }
+
+        Some(Self {
+            target: target.to_string(),
+            input: input.to_string(),
+            parameter: parameter.map(str::to_string),
+            delay,
+            times_to_fire,
+        })
+    }
+}
+
+/// The outputs of an entity, read from its properties whose key looks like an output (`OnTrigger`, `OnTimer`, ...).
+/// As an entity can't have the same key twice, an output can hold several connections separated by `;`,
+/// or be split over keys with a `#` suffix (`OnTrigger#2`).
+#[derive(Component, Default, Debug)]
+pub struct Outputs {
+    connections: Vec<(String, Connection)>,
+}
+
+impl Outputs {
+    pub fn from_properties(props: &MapEntityProperties) -> Self {
+        let mut connections = Vec::new();
+        for (key, value) in props.properties.iter() {
+            let output = key.split('#').next().unwrap_or_default();
+            if !is_output_name(output) {
+                continue;
+            }
+            for value in value.split(';').filter(|value| !value.trim().is_empty()) {
+                match Connection::parse(value) {
+                    Some(connection) => connections.push((output.to_string(), connection)),
+                    None => warn!(
+                        "{}: invalid connection for output `{}`: \"{}\"",
+                        props.classname, output, value
+                    ),
+                }
+            }
+        }
+        Self { connections }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.connections.is_empty()
+    }
+
+    /// The connections of an output
+    pub fn get<'a>(&'a self, output: &'a str) -> impl Iterator<Item = &'a Connection> + 'a {
+        self.connections
+            .iter()
+            .filter(move |(name, _)| name == output)
+            .map(|(_, connection)| connection)
+    }
+
+    pub fn add(&mut self, output: impl Into<String>, connection: Connection) {
+        self.connections.push((output.into(), connection));
+    }
+}
+
+/// `On` followed by an uppercase letter, like Source's outputs
+fn is_output_name(key: &str) -> bool {
+    key.strip_prefix("On")
+        .and_then(|rest| rest.chars().next())
+        .is_some_and(|c| c.is_ascii_uppercase())
+}
+
+/// Send to fire an output of an entity, every connection of the output sends its input once its delay has passed
+#[derive(Event, Debug, Clone)]
+pub struct FireOutput {
+    pub entity: Entity,
+    pub output: String,
+    pub activator: Entity,
+}
+
+/// Triggered on the target entities of a connection, to be handled by observers.
+/// The `Kill` input also despawns the entity, after its observers ran.
+///
+/// ```ignore
+/// commands.entity(door).observe(|trigger: Trigger<Input>, mut doors: Query<&mut Door>| {
+///     if trigger.input == "Open" {
+///         // open the door
+///     }
+/// });
+/// ```
+#[derive(Event, Debug, Clone)]
+pub struct Input {
+    pub input: String,
+    pub parameter: Option<String>,
+    /// whatever started the chain of outputs, e.g. the player entering a trigger
+    pub activator: Entity,
+    /// the entity that fired the output
+    pub caller: Entity,
+}
+
+/// Connections waiting out their delay
+#[derive(Resource, Default)]
+pub struct DelayedInputs {
+    pending: Vec<(Timer, PendingInput)>,
+}
+
+/// An input on its way to the entities named by a connection's target, in the map of the entity that fired it
+struct PendingInput {
+    target: String,
+    map: Option<Entity>,
+    input: Input,
+}
+
+/// Reads the outputs of newly spawned map entities
+pub fn insert_outputs_system(
+    mut commands: Commands,
+    map_entities: Query<(Entity, &MapEntityProperties), Added<MapEntityProperties>>,
+) {
+    for (entity, props) in map_entities.iter() {
+        let outputs = Outputs::from_properties(props);
+        if !outputs.is_empty() {
+            commands.entity(entity).insert(outputs);
+        }
+    }
+}
+
+/// Fires the `OnTrigger`, `OnStartTouch` and `OnEndTouch` outputs of triggers
+pub fn trigger_outputs_system(
+    mut triggered_events: EventReader<TriggeredEvent>,
+    mut entered_events: EventReader<TriggerEntered>,
+    mut exited_events: EventReader<TriggerExited>,
+    mut fire_output: EventWriter<FireOutput>,
+) {
+    for event in entered_events.read() {
+        fire_output.send(FireOutput {
+            entity: event.trigger,
+            output: "OnStartTouch".into(),
+            activator: event.instigator,
+        });
+    }
+    for event in triggered_events.read() {
+        fire_output.send(FireOutput {
+            entity: event.trigger,
+            output: "OnTrigger".into(),
+            activator: event.triggered_by,
+        });
+    }
+    for event in exited_events.read() {
+        fire_output.send(FireOutput {
+            entity: event.trigger,
+            output: "OnEndTouch".into(),
+            activator: event.instigator,
+        });
+    }
+}
+
+/// Sends the inputs of every fired output once their delay has passed
+pub fn fire_outputs_system(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut fire_output: EventReader<FireOutput>,
+    mut outputs: Query<&mut Outputs>,
+    mut delayed: ResMut<DelayedInputs>,
+    target_names: Res<TargetNames>,
+    map_of: MapOf,
+) {
+    for (timer, _) in delayed.pending.iter_mut() {
+        timer.tick(time.delta());
+    }
+    let (finished, waiting) = std::mem::take(&mut delayed.pending)
+        .into_iter()
+        .partition::<Vec<_>, _>(|(timer, _)| timer.finished());
+    delayed.pending = waiting;
+    let mut ready = finished
+        .into_iter()
+        .map(|(_, pending)| pending)
+        .collect::<Vec<_>>();
+
+    for event in fire_output.read() {
+        let Ok(mut outputs) = outputs.get_mut(event.entity) else {
+            continue;
+        };
+        for (output, connection) in outputs.connections.iter_mut() {
+            if *output != event.output || connection.times_to_fire == Some(0) {
+                continue;
+            }
+            if let Some(times_to_fire) = connection.times_to_fire.as_mut() {
+                *times_to_fire -= 1;
+            }
+
+            let pending = PendingInput {
+                target: connection.target.clone(),
+                map: map_of.get(event.entity),
+                input: Input {
+                    input: connection.input.clone(),
+                    parameter: connection.parameter.clone(),
+                    activator: event.activator,
+                    caller: event.entity,
+                },
+            };
+            if connection.delay.is_zero() {
+                ready.push(pending);
+            } else {
+                delayed
+                    .pending
+                    .push((Timer::new(connection.delay, TimerMode::Once), pending));
+            }
+        }
+    }
+
+    for PendingInput { target, map, input } in ready {
+        let targets = match target.as_str() {
+            "!self" | "!caller" => vec![input.caller],
+            "!activator" => vec![input.activator],
+            _ => target_names.get(map, &target).collect(),
+        };
+        for entity in targets {
+            let kill = input.input.eq_ignore_ascii_case("Kill");
+            commands.trigger_targets(input.clone(), entity);
+            if let (true, Some(entity)) = (kill, commands.get_entity(entity)) {
+                entity.despawn_recursive();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection(target: &str, input: &str) -> Connection {
+        Connection {
+            target: target.to_string(),
+            input: input.to_string(),
+            parameter: None,
+            delay: Duration::ZERO,
+            times_to_fire: None,
+        }
+    }
+
+    #[test]
+    fn parses_every_field() {
+        let expected = Connection {
+            parameter: Some("red".to_string()),
+            delay: Duration::from_millis(1500),
+            times_to_fire: Some(2),
+            ..connection("lamp", "Color")
+        };
+        assert_eq!(
+            Connection::parse("lamp,Color,red,1.5,2"),
+            Some(expected.clone())
+        );
+        assert_eq!(
+            Connection::parse("lamp\x1bColor\x1bred\x1b1.5\x1b2"),
+            Some(expected)
+        );
+    }
+
+    #[test]
+    fn missing_fields_take_their_defaults() {
+        assert_eq!(
+            Connection::parse("door,Open"),
+            Some(connection("door", "Open"))
+        );
+        assert_eq!(
+            Connection::parse("door\x1bOpen"),
+            Some(connection("door", "Open"))
+        );
+        // empty fields in between are missing too, and -1 fires forever
+        assert_eq!(
+            Connection::parse(" door , Open , , 2 , -1 "),
+            Some(Connection {
+                delay: Duration::from_secs(2),
+                ..connection("door", "Open")
+            })
+        );
+        assert_eq!(
+            Connection::parse("door\x1bOpen\x1b\x1b\x1b1"),
+            Some(Connection {
+                times_to_fire: Some(1),
+                ..connection("door", "Open")
+            })
+        );
+    }
+
+    #[test]
+    fn escape_separated_parameters_can_have_commas() {
+        assert_eq!(
+            Connection::parse("sign\x1bSetText\x1bhello, world"),
+            Some(Connection {
+                parameter: Some("hello, world".to_string()),
+                ..connection("sign", "SetText")
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_connections() {
+        for value in [
+            "",
+            "door",
+            "door,",
+            ",Open",
+            "door\x1b\x1b",
+            "door,Open,,soon",
+            "door,Open,,-1",
+            "door,Open,,0,once",
+            "door,Open,,0,1,extra",
+        ] {
+            assert_eq!(Connection::parse(value), None, "{value:?}");
+        }
+    }
+}
@@ -3,25 +3,48 @@ use bevy::asset::{AssetLoader, Handle, LoadContext};
 use bevy::prelude::*;
 use bevy::reflect::TypePath;
 use components::MapUnits;
+use settings::MapLoaderSettings;
 use std::collections::BTreeMap;
 use thiserror::Error;
 use tracing::info;
 
 pub mod auto_create_config;
 pub mod build;
+pub mod classes;
 pub mod components;
 pub mod conversions;
+pub mod culling;
+pub mod environment;
 pub mod gameplay_systems;
+pub mod io;
 pub mod load;
+pub mod material;
+pub mod normals;
+pub mod paths;
+pub mod settings;
+pub mod targets;
+pub mod textures;
+pub mod uv;
+pub mod wad;
 
 #[derive(Debug, Asset, TypePath)]
 pub struct MapAsset {
     geomap: Option<shambler::GeoMap>,
     texture_sizes: BTreeMap<String, (u32, u32)>,
+    texture_handles: BTreeMap<String, Handle<Image>>,
     material_handles: BTreeMap<String, Handle<StandardMaterial>>,
+    format: uv::MapFormat,
+    batching: settings::MeshBatching,
+    world_classnames: Vec<String>,
+    remove_hidden_faces: bool,
 }
 
 impl MapAsset {
+    /// The texture coordinate format detected when loading the map
+    pub fn format(&self) -> uv::MapFormat {
+        self.format
+    }
+
     pub fn get_texture_names_with_size(&self) -> BTreeMap<&str, (u32, u32)> {
         let mut names: BTreeMap<&str, (u32, u32)> = BTreeMap::new();
         for (texture_name, (width, height)) in &self.texture_sizes {
@@ -29,28 +52,75 @@ impl MapAsset {
         }
         names
     }
+
+    /// Everything the loader knows about a texture, `None` if it couldn't be loaded
+    pub fn get_texture<'a>(&'a self, texture_name: &'a str) -> Option<material::MapTexture<'a>> {
+        Some(material::MapTexture {
+            name: texture_name,
+            image: self.texture_handles.get(texture_name)?,
+            standard_material: self.material_handles.get(texture_name)?,
+            size: *self.texture_sizes.get(texture_name)?,
+        })
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum MapAssetLoaderError {
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
+    #[error("map is not valid utf8: {0}")]
+    Encoding(#[from] std::str::Utf8Error),
+    #[error("could not parse map at line {line}, column {column}: `{snippet}`")]
+    Parse {
+        line: usize,
+        column: usize,
+        /// the text of the line the error happened on
+        snippet: String,
+    },
+    #[error("entity {entity} ({classname}) has an invalid `{key}` property: \"{value}\"")]
+    InvalidEntityProperty {
+        entity: usize,
+        classname: String,
+        key: String,
+        value: String,
+    },
+    #[error("entity {entity} has no properties")]
+    MissingEntityProperties { entity: usize },
+    #[error("brush {brush} of entity {entity} ({classname}) is degenerate")]
+    DegenerateBrush {
+        entity: usize,
+        brush: usize,
+        classname: String,
+    },
 }
 
-#[derive(Default)]
-pub struct MapAssetLoader;
+/// Loads maps along with their textures, which get their materials from the plugin's `TextureRules`
+pub struct MapAssetLoader {
+    texture_rules: textures::TextureRules,
+}
+
+impl FromWorld for MapAssetLoader {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            texture_rules: world
+                .get_resource::<textures::TextureRules>()
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+}
 
 impl AssetLoader for MapAssetLoader {
     type Asset = MapAsset;
-    type Settings = ();
+    type Settings = MapLoaderSettings;
     type Error = MapAssetLoaderError;
     async fn load(
         &self,
         reader: &mut dyn Reader,
-        _settings: &Self::Settings,
+        settings: &Self::Settings,
         load_context: &mut LoadContext<'_>,
     ) -> Result<Self::Asset, Self::Error> {
-        load::load(reader, load_context, false).await
+        load::load(reader, settings, load_context, Some(&self.texture_rules)).await
     }
 
     fn extensions(&self) -> &[&str] {
@@ -63,15 +133,15 @@ pub struct HeadlessMapAssetLoader;
 
 impl AssetLoader for HeadlessMapAssetLoader {
     type Asset = MapAsset;
-    type Settings = ();
+    type Settings = MapLoaderSettings;
     type Error = MapAssetLoaderError;
     async fn load(
         &self,
         reader: &mut dyn Reader,
-        _settings: &Self::Settings,
+        settings: &Self::Settings,
         load_context: &mut LoadContext<'_>,
     ) -> Result<Self::Asset, Self::Error> {
-        load::load(reader, load_context, true).await
+        load::load(reader, settings, load_context, None).await
     }
 
     fn extensions(&self) -> &[&str] {
@@ -83,30 +153,172 @@ impl AssetLoader for HeadlessMapAssetLoader {
 pub struct PostBuildMapEvent {
     pub map: Entity,
 }
+
+/// Sent for every entity or brush that was skipped while building a map.
+/// Errors that stop the map from loading at all are reported through Bevy's `AssetLoadFailedEvent<MapAsset>` instead.
+#[derive(Event, Debug)]
+pub struct MapBuildErrorEvent {
+    pub map: Entity,
+    pub error: MapAssetLoaderError,
+}
 #[derive(Default)]
 pub struct MapAssetLoaderPlugin {
     /// If true, the plugin will not add meshes, only colliders
     pub headless: bool,
     pub units: MapUnits,
+    /// What faces do depending on their texture, hiding `clip`, `trigger`, `skip`... by default
+    pub texture_rules: textures::TextureRules,
+    /// How brushes become colliders, unless their entity's `_collider` key says otherwise
+    pub collider: components::ColliderShape,
 }
 
 impl Plugin for MapAssetLoaderPlugin {
     fn build(&self, app: &mut App) {
         app.init_asset::<MapAsset>()
+            .init_asset::<wad::WadAsset>()
+            .init_asset_loader::<wad::WadAssetLoader>()
             .add_event::<components::TriggeredEvent>()
+            .add_event::<components::TriggerEntered>()
+            .add_event::<components::TriggerExited>()
             .add_event::<PostBuildMapEvent>()
+            .add_event::<MapBuildErrorEvent>()
             .add_event::<build::SpawnMeshEvent>();
 
-        app.insert_resource(self.units.clone());
+        app.insert_resource(self.units.clone())
+            .insert_resource(self.texture_rules.clone())
+            .insert_resource(self.collider);
+
+        // entities are set up by the handler of their classname once the map is built,
+        // classes the app registered before adding the plugin win over the builtin ones
+        classes::register_builtin_classes(
+            &mut app
+                .world_mut()
+                .get_resource_or_insert_with(classes::MapClassRegistry::default),
+        );
+        app.add_systems(
+            PreUpdate,
+            classes::map_class_system.after(load::handle_loaded_map_system),
+        );
+
+        // instigators set off triggers through the contacts of the physics backend
+        #[cfg(feature = "avian")]
+        app.add_systems(Update, gameplay_systems::avian_trigger_system);
+        #[cfg(feature = "rapier")]
+        #[cfg(not(feature = "avian"))]
+        app.add_systems(Update, gameplay_systems::rapier_trigger_system);
+
+        // triggers fire every entity named by their target
+        app.add_event::<targets::Activated>()
+            .add_event::<targets::TargetMessage>()
+            .init_resource::<targets::TargetNames>()
+            .init_resource::<targets::DelayedTargets>()
+            .add_observer(targets::add_target_name_observer)
+            .add_observer(targets::remove_target_name_observer)
+            .add_systems(PostUpdate, targets::use_targets_system)
+            .add_systems(
+                PostUpdate,
+                (
+                    gameplay_systems::logic_activation_system,
+                    gameplay_systems::logic_timer_system,
+                )
+                    .chain()
+                    .after(targets::use_targets_system),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    gameplay_systems::door_setup_system,
+                    gameplay_systems::door_activation_system,
+                )
+                    .chain()
+                    .after(targets::use_targets_system)
+                    .before(bevy::transform::TransformSystem::TransformPropagate),
+            );
+
+        // trains follow the path corners linked when a map is built
+        app.init_resource::<paths::PathGraph>().add_systems(
+            PostUpdate,
+            (
+                gameplay_systems::train_setup_system,
+                gameplay_systems::train_activation_system,
+            )
+                .chain()
+                .after(targets::use_targets_system)
+                .before(bevy::transform::TransformSystem::TransformPropagate),
+        );
+        // liquids are triggers of their own, telling how deep instigators are in them
+        app.add_event::<components::LiquidEntered>()
+            .add_event::<components::LiquidExited>()
+            .add_event::<components::LiquidDepth>()
+            .add_systems(PostUpdate, gameplay_systems::liquid_system);
+
+        // doors, trains and rotators move once they know where they're going.
+        // Avian moves their bodies by their velocities on its fixed timestep, so they're set on that same step
+        let movers = (
+            gameplay_systems::door_move_system,
+            gameplay_systems::train_move_system,
+            gameplay_systems::rotating_system,
+        );
+        #[cfg(feature = "avian")]
+        app.add_systems(FixedUpdate, movers);
+        #[cfg(not(feature = "avian"))]
+        app.add_systems(
+            PostUpdate,
+            movers
+                .after(gameplay_systems::door_activation_system)
+                .after(gameplay_systems::train_activation_system)
+                .before(bevy::transform::TransformSystem::TransformPropagate),
+        );
+
+        // the worldspawn's sky, lighting and fog
+        app.add_systems(
+            PostUpdate,
+            (
+                environment::environment_setup_system,
+                environment::camera_fog_system,
+            ),
+        );
+
+        // outputs send inputs to the entities they target
+        app.add_event::<io::FireOutput>()
+            .init_resource::<io::DelayedInputs>()
+            .add_systems(
+                PreUpdate,
+                io::insert_outputs_system.after(load::handle_loaded_map_system),
+            )
+            .add_systems(
+                PostUpdate,
+                (io::trigger_outputs_system, io::fire_outputs_system).chain(),
+            );
+
+        // registered qevy entities are spawned from the map's properties
+        app.init_resource::<auto_create_config::QevyRegistry>()
+            .add_systems(
+                PreUpdate,
+                auto_create_config::spawn::spawn_qevy_entities_system
+                    .after(load::handle_loaded_map_system),
+            );
+        if !app
+            .is_plugin_added::<auto_create_config::register_types::properties::QevyPropertyPlugin>()
+        {
+            app.add_plugins(auto_create_config::register_types::properties::QevyPropertyPlugin);
+        }
 
         if self.headless {
             info!("Using headless map loader. Only colliders will be added.");
             app.add_systems(PreUpdate, load::handle_loaded_map_system);
             app.init_asset_loader::<HeadlessMapAssetLoader>();
         } else {
+            app.init_resource::<material::StandardMapMaterial>();
+            app.add_systems(PostUpdate, environment::sky_system);
             app.add_systems(
                 PreUpdate,
-                (load::handle_loaded_map_system, build::mesh_spawn_system).chain(),
+                (
+                    load::handle_loaded_map_system,
+                    build::mesh_spawn_system::<material::StandardMapMaterial>
+                        .run_if(not(resource_exists::<material::CustomMapMaterial>)),
+                )
+                    .chain(),
             );
             app.init_asset_loader::<MapAssetLoader>();
         }
@@ -1,14 +1,21 @@
-use crate::build::SpawnMeshEvent;
+use crate::build::{MapBuildEvents, MapBuildSettings};
+use crate::material::{
+    MaterialSidecar, EMISSIVE_MAP_SUFFIX, METALLIC_ROUGHNESS_MAP_SUFFIX, NORMAL_MAP_SUFFIX,
+    OCCLUSION_MAP_SUFFIX, SIDECAR_EXTENSION,
+};
+use crate::settings::{join_asset_path, MapLoaderSettings};
+use crate::textures::TextureRules;
+use crate::uv::MapFormat;
+use crate::wad::{load_palette, Wad, WadVersion};
 use crate::{components::*, MapAssetLoaderError};
-use crate::{MapAsset, PostBuildMapEvent};
+use crate::{MapAsset, MapBuildErrorEvent};
 use bevy::asset::io::Reader;
 use bevy::asset::LoadContext;
 use bevy::asset::LoadedAsset;
-use bevy::image::{
-    CompressedImageFormats, ImageAddressMode, ImageSampler, ImageSamplerDescriptor, ImageType,
-};
+use bevy::image::{CompressedImageFormats, ImageSampler, ImageType};
 use bevy::prelude::*;
 use bevy::render::render_asset::RenderAssetUsages;
+use bevy::utils::HashSet;
 use std::collections::BTreeMap;
 
 pub(crate) fn extensions() -> &'static [&'static str] {
@@ -17,113 +24,520 @@ pub(crate) fn extensions() -> &'static [&'static str] {
 
 pub(crate) async fn load<'a>(
     reader: &'a mut dyn Reader,
+    settings: &'a MapLoaderSettings,
     load_context: &'a mut LoadContext<'_>,
-    headless: bool,
+    texture_rules: Option<&'a TextureRules>,
 ) -> Result<MapAsset, MapAssetLoaderError> {
     let mut bytes = Vec::new();
     reader.read_to_end(&mut bytes).await?;
-    if let Ok(map) = std::str::from_utf8(&bytes)
-        .expect("invalid utf8")
-        .parse::<shalrath::repr::Map>()
-    {
-        let geomap = Some(shambler::GeoMap::new(map.clone()));
-        let mut map = MapAsset {
-            geomap: geomap,
-            texture_sizes: BTreeMap::new(),
-            material_handles: BTreeMap::new(),
+    let map = parse_map(std::str::from_utf8(&bytes)?)?;
+
+    let geomap = shambler::GeoMap::new(map);
+    let format = MapFormat::detect(&geomap);
+    debug!(
+        "loading {:?} format map {}",
+        format,
+        load_context.path().display()
+    );
+    let mut map = MapAsset {
+        geomap: Some(geomap),
+        texture_sizes: BTreeMap::new(),
+        texture_handles: BTreeMap::new(),
+        material_handles: BTreeMap::new(),
+        format,
+        batching: settings.batching,
+        world_classnames: settings.world_classnames.clone(),
+        remove_hidden_faces: settings.remove_hidden_faces,
+    };
+
+    // headless loaders don't have rules, they don't load textures at all
+    if let Some(texture_rules) = texture_rules {
+        load_map_textures(&mut map, settings, texture_rules, load_context).await;
+    }
+    Ok(map)
+}
+
+/// Parses the whole map, failing with the location of the first thing we couldn't read.
+/// shalrath's `FromStr` for `Map` stops at the first broken entity or blank line and returns everything
+/// before it, which would silently drop the rest of the map.
+pub(crate) fn parse_map(text: &str) -> Result<shalrath::repr::Map, MapAssetLoaderError> {
+    let mut entities = Vec::new();
+    let mut rest = text;
+    loop {
+        rest = skip_blanks_and_comments(rest);
+        if rest.is_empty() {
+            return Ok(shalrath::repr::Map::new(entities));
+        }
+
+        // keep going from wherever shalrath stopped, as long as it read something
+        if let Ok((remaining, map)) = shalrath::parser::repr::parse_map(rest) {
+            if remaining.len() < rest.len() {
+                entities.extend(map.0);
+                rest = remaining;
+                continue;
+            }
+        }
+
+        // parse the entity we stopped at on its own to find out where it went wrong
+        let remaining = match rest.parse::<shalrath::repr::Entity>() {
+            Err(err) => err.input.len(),
+            Ok(_) => rest.len(),
+        };
+        let offset = text.len() - remaining;
+        return Err(parse_error_at(
+            text,
+            find_broken_line(text, offset).unwrap_or(offset),
+        ));
+    }
+}
+
+fn skip_blanks_and_comments(mut text: &str) -> &str {
+    loop {
+        text = text.trim_start();
+        if !text.starts_with("//") {
+            return text;
+        }
+        text = text.split_once('\n').map_or("", |(_, rest)| rest);
+    }
+}
+
+/// nom backtracks to the start of the brush or entity that failed,
+/// so look through its lines for the face or property that is actually broken.
+fn find_broken_line(text: &str, offset: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut line_start = offset;
+    for line in text[offset..].split_inclusive('\n') {
+        let trimmed = line.trim();
+        let indent = line.len() - line.trim_start().len();
+        let remaining = if trimmed.starts_with('(') {
+            trimmed
+                .parse::<shalrath::repr::BrushPlane>()
+                .err()
+                .map(|err| err.input.len())
+        } else if trimmed.starts_with('"') {
+            trimmed
+                .parse::<shalrath::repr::Property>()
+                .err()
+                .map(|err| err.input.len())
+        } else {
+            None
         };
+        if let Some(remaining) = remaining {
+            return Some(line_start + indent + trimmed.len() - remaining);
+        }
 
-        if !headless {
-            load_map_textures(&mut map, load_context).await;
+        match trimmed {
+            "{" => depth += 1,
+            "}" => depth -= 1,
+            _ => {}
+        }
+        if depth <= 0 {
+            return None;
         }
-        return Ok(map);
+        line_start += line.len();
     }
-    Err(MapAssetLoaderError::Io(std::io::Error::new(
-        std::io::ErrorKind::InvalidData,
-        "invalid map",
-    )))
+    None
 }
 
+fn parse_error_at(text: &str, offset: usize) -> MapAssetLoaderError {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line_end = text[offset..]
+        .find('\n')
+        .map(|i| offset + i)
+        .unwrap_or(text.len());
+
+    MapAssetLoaderError::Parse {
+        line: before.matches('\n').count() + 1,
+        column: before[line_start..].chars().count() + 1,
+        snippet: text[line_start..line_end].trim().to_string(),
+    }
+}
+
+/// Builds every `Map` whose asset finished loading or was hot reloaded, and clears the ones whose asset was removed.
+/// Maps spawned after their asset already loaded (e.g. a second `Map` sharing a handle) are built right away.
 pub(crate) fn handle_loaded_map_system(
-    map_units: Res<MapUnits>,
+    settings: MapBuildSettings,
     mut commands: Commands,
-    mut map_assets: ResMut<Assets<MapAsset>>,
+    asset_server: Res<AssetServer>,
+    map_assets: Res<Assets<MapAsset>>,
     mut ev_asset: EventReader<AssetEvent<MapAsset>>,
-    mut q_maps: Query<Entity, With<Map>>,
-    mut post_build_event: EventWriter<PostBuildMapEvent>,
-    mut spawn_mesh_event: EventWriter<SpawnMeshEvent>,
+    q_maps: Query<(Entity, Ref<Map>)>,
+    mut events: MapBuildEvents,
 ) {
+    let mut rebuilt = HashSet::new();
+    let mut removed = HashSet::new();
     for ev in ev_asset.read() {
         match ev {
-            AssetEvent::LoadedWithDependencies { id } => {
-                for map_entity in q_maps.iter_mut() {
-                    commands.entity(map_entity).despawn_descendants();
-                    let map_asset = map_assets.get_mut(*id).unwrap();
-                    crate::build::build_map(
-                        &map_units,
-                        map_entity,
-                        map_asset,
-                        &mut commands,
-                        &mut spawn_mesh_event,
-                        &mut post_build_event,
-                    );
-                }
+            AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } => {
+                rebuilt.insert(*id);
+            }
+            AssetEvent::Removed { id } => {
+                removed.insert(*id);
             }
             _ => {}
         }
     }
+
+    for (map_entity, map) in q_maps.iter() {
+        let id = map.asset.id();
+        if removed.contains(&id) {
+            commands.entity(map_entity).despawn_descendants();
+            continue;
+        }
+
+        let should_build = rebuilt.contains(&id)
+            || (map.is_changed() && asset_server.is_loaded_with_dependencies(id));
+        if !should_build {
+            continue;
+        }
+
+        let Some(map_asset) = map_assets.get(id) else {
+            continue;
+        };
+
+        commands.entity(map_entity).despawn_descendants();
+        let errors =
+            crate::build::build_map(&settings, map_entity, map_asset, &mut commands, &mut events);
+        for error in errors {
+            warn!("skipped while building map: {}", error);
+            events.build_error.send(MapBuildErrorEvent {
+                map: map_entity,
+                error,
+            });
+        }
+    }
 }
 
 pub(crate) async fn load_map_textures<'a>(
     map_asset: &mut MapAsset,
+    settings: &MapLoaderSettings,
+    texture_rules: &TextureRules,
     load_context: &mut LoadContext<'a>,
 ) {
     let geomap = map_asset.geomap.as_mut().unwrap();
+    let wads = load_map_wads(geomap, settings, load_context).await;
+    let mut palette = None;
 
     // for each texture, load it into the asset server
     for texture_info in geomap.textures.iter() {
         let texture_name = texture_info.1;
-        let file = format!("textures/{}.png", texture_name);
+        let sidecar = load_material_sidecar(texture_name, settings, load_context).await;
 
-        let bytes = load_context.read_asset_bytes(&file).await;
+        // the sidecar can point to a base color texture of its own,
+        // otherwise the first loose file that exists and decodes wins
+        let base_color_paths = match sidecar.as_ref() {
+            Some((directory, sidecar)) => match sidecar.base_color_texture.as_ref() {
+                Some(path) => vec![join_asset_path(directory, path)],
+                None => settings.texture_paths(texture_name),
+            },
+            None => settings.texture_paths(texture_name),
+        };
+        let mut texture = load_image(&base_color_paths, true, settings, load_context).await;
 
-        if let Ok(bytes) = bytes {
-            let texture = Image::from_buffer(
-                &bytes,
-                ImageType::Extension("png"),
-                CompressedImageFormats::all(),
+        // then the wads, in the order they were listed
+        if texture.is_none() {
+            for wad in wads.iter() {
+                if palette.is_none() && wad.version() == WadVersion::Wad2 {
+                    palette = Some(match wad.palette() {
+                        Some(palette) => Some(palette),
+                        None => load_palette(&settings.palette, load_context).await,
+                    });
+                }
+
+                match wad.decode_texture(texture_name, palette.as_ref().and_then(Option::as_ref)) {
+                    Some(Ok(wad_texture)) => {
+                        texture = Some(wad_texture.into_image(settings.texture_filtering));
+                        break;
+                    }
+                    Some(Err(e)) => warn!("could not decode wad texture {}: {}", texture_name, e),
+                    None => {}
+                }
+            }
+        }
+
+        if let Some(texture) = texture {
+            let texture_size = (texture.width(), texture.height());
+            let texture_handle = load_context.add_loaded_labeled_asset(
+                format!("textures/{}", texture_name),
+                LoadedAsset::from(texture),
+            );
+            let mut mat = settings.material.to_material(texture_handle.clone());
+            // `{` textures are see-through where they use the transparent color
+            if texture_name.starts_with('{') {
+                mat.alpha_mode = AlphaMode::Mask(0.5);
+            }
+            // liquids are see-through and seen from below their surface
+            if texture_rules.get(texture_name).liquid.is_some() {
+                mat.base_color = Color::WHITE.with_alpha(settings.material.liquid_alpha);
+                mat.alpha_mode = AlphaMode::Blend;
+                mat.double_sided = true;
+                mat.cull_mode = None;
+            }
+
+            let sidecar_path = |path: fn(&MaterialSidecar) -> &Option<String>| {
+                sidecar.as_ref().and_then(|(directory, sidecar)| {
+                    path(sidecar)
+                        .as_ref()
+                        .map(|path| join_asset_path(directory, path))
+                })
+            };
+            mat.normal_map_texture = load_material_map(
+                texture_name,
+                NORMAL_MAP_SUFFIX,
+                sidecar_path(|sidecar| &sidecar.normal_map),
+                false,
+                settings,
+                load_context,
+            )
+            .await;
+            mat.metallic_roughness_texture = load_material_map(
+                texture_name,
+                METALLIC_ROUGHNESS_MAP_SUFFIX,
+                sidecar_path(|sidecar| &sidecar.metallic_roughness_texture),
+                false,
+                settings,
+                load_context,
+            )
+            .await;
+            mat.emissive_texture = load_material_map(
+                texture_name,
+                EMISSIVE_MAP_SUFFIX,
+                sidecar_path(|sidecar| &sidecar.emissive_texture),
+                true,
+                settings,
+                load_context,
+            )
+            .await;
+            mat.occlusion_texture = load_material_map(
+                texture_name,
+                OCCLUSION_MAP_SUFFIX,
+                sidecar_path(|sidecar| &sidecar.occlusion_texture),
                 false,
-                ImageSampler::Descriptor(ImageSamplerDescriptor {
-                    address_mode_u: ImageAddressMode::Repeat,
-                    address_mode_v: ImageAddressMode::Repeat,
-                    ..default()
-                }),
-                RenderAssetUsages::RENDER_WORLD,
+                settings,
+                load_context,
+            )
+            .await;
+
+            // the texture scales these, so the map itself decides how rough and metallic the surface is
+            if mat.metallic_roughness_texture.is_some() {
+                mat.metallic = 1.0;
+                mat.perceptual_roughness = 1.0;
+            }
+            // the emissive color multiplies the texture, black would hide it
+            if mat.emissive_texture.is_some() {
+                mat.emissive = LinearRgba::WHITE;
+            }
+            if let Some((_, sidecar)) = sidecar.as_ref() {
+                sidecar.apply(&mut mat);
+            }
+
+            let mat_handle = load_context.add_loaded_labeled_asset::<StandardMaterial>(
+                format!("materials/{}", texture_name),
+                LoadedAsset::from(mat),
             );
+            map_asset
+                .material_handles
+                .insert(texture_name.clone(), mat_handle);
+            map_asset
+                .texture_handles
+                .insert(texture_name.clone(), texture_handle);
+            map_asset
+                .texture_sizes
+                .insert(texture_name.clone(), texture_size);
+        }
+    }
+}
+
+/// Reads and decodes the first of `paths` that exists, the format is picked from the extension
+async fn load_image<'a>(
+    paths: &[String],
+    is_srgb: bool,
+    settings: &MapLoaderSettings,
+    load_context: &mut LoadContext<'a>,
+) -> Option<Image> {
+    for path in paths {
+        let Ok(bytes) = load_context.read_asset_bytes(path).await else {
+            continue;
+        };
+
+        let extension = path.rsplit('.').next().unwrap_or_default();
+        match Image::from_buffer(
+            &bytes,
+            ImageType::Extension(extension),
+            CompressedImageFormats::all(),
+            is_srgb,
+            ImageSampler::Descriptor(settings.texture_filtering.sampler_descriptor()),
+            RenderAssetUsages::RENDER_WORLD,
+        ) {
+            Ok(image) => return Some(image),
+            Err(e) => warn!("could not decode texture {}: {}", path, e),
+        }
+    }
+    None
+}
 
-            if texture.is_ok() {
-                let texture = texture.unwrap();
-                let texture_handle = load_context.add_loaded_labeled_asset(
-                    format!("textures/{}", texture_name),
-                    LoadedAsset::from(texture.clone()),
-                );
-                let mat = StandardMaterial {
-                    base_color_texture: Some(texture_handle),
-                    perceptual_roughness: 0.55,
-                    metallic: 0.5,
-                    ..default()
-                };
-                let mat_handle = load_context.add_loaded_labeled_asset::<StandardMaterial>(
-                    format!("materials/{}", texture_name),
-                    LoadedAsset::from(mat),
-                );
-                map_asset
-                    .material_handles
-                    .insert(texture_name.clone(), mat_handle);
-                map_asset
-                    .texture_sizes
-                    .insert(texture_name.clone(), (texture.width(), texture.height()));
+/// Looks for `<texture>.material.ron` in the texture roots, returning it along with its directory
+async fn load_material_sidecar<'a>(
+    texture_name: &str,
+    settings: &MapLoaderSettings,
+    load_context: &mut LoadContext<'a>,
+) -> Option<(String, MaterialSidecar)> {
+    let file = format!("{}.{}", texture_name, SIDECAR_EXTENSION);
+    for root in settings.texture_roots.iter() {
+        let path = join_asset_path(root, &file);
+        let Ok(bytes) = load_context.read_asset_bytes(&path).await else {
+            continue;
+        };
+
+        match ron::de::from_bytes::<MaterialSidecar>(&bytes) {
+            Ok(sidecar) => {
+                // texture names can contain directories, e.g. `common/clip`
+                let directory = path
+                    .rsplit_once('/')
+                    .map(|(directory, _)| directory.to_string())
+                    .unwrap_or_default();
+                return Some((directory, sidecar));
             }
+            Err(e) => warn!("could not read material sidecar {}: {}", path, e),
+        }
+    }
+    None
+}
+
+/// Loads one of the extra maps of a material, from the sidecar if it names one,
+/// otherwise from a file next to the texture with the given suffix.
+/// Only color maps are `is_srgb`, the others hold linear data
+async fn load_material_map<'a>(
+    texture_name: &str,
+    suffix: &str,
+    sidecar_path: Option<String>,
+    is_srgb: bool,
+    settings: &MapLoaderSettings,
+    load_context: &mut LoadContext<'a>,
+) -> Option<Handle<Image>> {
+    let map_name = format!("{}{}", texture_name, suffix);
+    let paths = match sidecar_path {
+        Some(path) => vec![path],
+        None => settings.texture_paths(&map_name),
+    };
+
+    let image = load_image(&paths, is_srgb, settings, load_context).await?;
+    Some(
+        load_context
+            .add_loaded_labeled_asset(format!("textures/{}", map_name), LoadedAsset::from(image)),
+    )
+}
+
+/// Reads the wads named in worldspawn's `wad` key (`;` separated), followed by the ones from the settings
+async fn load_map_wads<'a>(
+    geomap: &shambler::GeoMap,
+    settings: &MapLoaderSettings,
+    load_context: &mut LoadContext<'a>,
+) -> Vec<Wad> {
+    let worldspawn_wads = geomap
+        .entity_properties
+        .values()
+        .find(|props| {
+            props
+                .iter()
+                .any(|p| p.key == "classname" && p.value == "worldspawn")
+        })
+        .and_then(|props| {
+            props
+                .iter()
+                .find(|p| p.key == "wad" || p.key == "_wad")
+                .map(|p| p.value.clone())
+        })
+        .unwrap_or_default();
+
+    let mut candidates = worldspawn_wads
+        .split(';')
+        .filter(|wad| !wad.trim().is_empty())
+        .map(|wad| settings.wad_paths(wad))
+        .collect::<Vec<_>>();
+    candidates.extend(settings.wads.iter().map(|wad| vec![wad.clone()]));
+
+    let mut wads = Vec::new();
+    for paths in candidates {
+        let mut found = false;
+        for path in paths.iter() {
+            let Ok(bytes) = load_context.read_asset_bytes(path).await else {
+                continue;
+            };
+
+            found = true;
+            match Wad::parse(bytes) {
+                Ok(wad) => wads.push(wad),
+                Err(e) => warn!("could not read wad {}: {}", path, e),
+            }
+            break;
+        }
+
+        if !found {
+            warn!("could not find wad, tried {:?}", paths);
+        }
+    }
+    wads
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BRUSH: &str = "( 0 0 0 ) ( 0 1 0 ) ( 1 0 0 ) base 0 0 0 1 1";
+
+    #[test]
+    fn parses_a_valid_map() {
+        let text = format!("{{\n\"classname\" \"worldspawn\"\n{{\n{BRUSH}\n}}\n}}\n");
+        assert_eq!(parse_map(&text).unwrap().0.len(), 1);
+    }
+
+    #[test]
+    fn parses_entities_separated_by_blank_lines_and_comments() {
+        let text = format!(
+            "{{\n\"classname\" \"worldspawn\"\n{{\n{BRUSH}\n}}\n}}\n\n\n// light\n//\n{{\n\"classname\" \"light\"\n}}\n\n{{\n\"classname\" \"light\"\n}}\n\n"
+        );
+        assert_eq!(parse_map(&text).unwrap().0.len(), 3);
+    }
+
+    #[test]
+    fn reports_the_line_and_column_of_a_broken_face() {
+        let text = format!(
+            "{{\n\"classname\" \"worldspawn\"\n{{\n{BRUSH}\n  ( 0 0 0 ) ( 0 x 0 ) ( 1 0 0 ) base 0 0 0 1 1\n}}\n}}\n"
+        );
+        match parse_map(&text) {
+            Err(MapAssetLoaderError::Parse {
+                line,
+                column,
+                snippet,
+            }) => {
+                assert_eq!((line, column), (5, 17));
+                assert_eq!(snippet, "( 0 0 0 ) ( 0 x 0 ) ( 1 0 0 ) base 0 0 0 1 1");
+            }
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn reports_the_line_of_a_broken_property() {
+        let text = "{\n\"classname\" \"worldspawn\"\n}\n{\n\"classname\" \"light\n\"origin\" \"0 0 0\"\n}\n";
+        // the unclosed quote runs on into the next line, which is where parsing fails
+        match parse_map(text) {
+            Err(MapAssetLoaderError::Parse { line, snippet, .. }) => {
+                assert_eq!(line, 6);
+                assert_eq!(snippet, "\"origin\" \"0 0 0\"");
+            }
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn reports_a_broken_entity_after_a_blank_line() {
+        let text = "{\n\"classname\" \"worldspawn\"\n}\n\n{\n\"classname\" \"light\n\"origin\" \"0 0 0\"\n}\n";
+        match parse_map(text) {
+            Err(MapAssetLoaderError::Parse { line, .. }) => assert_eq!(line, 7),
+            other => panic!("expected a parse error, got {other:?}"),
         }
     }
 }
@@ -0,0 +1,241 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::build::mesh_spawn_system;
+
+/// Extra maps picked up next to a texture by their suffix, e.g. `textures/brick_normal.png` for `brick`
+pub const NORMAL_MAP_SUFFIX: &str = "_normal";
+/// glTF layout, roughness in green and metallic in blue
+pub const METALLIC_ROUGHNESS_MAP_SUFFIX: &str = "_mr";
+pub const EMISSIVE_MAP_SUFFIX: &str = "_emissive";
+pub const OCCLUSION_MAP_SUFFIX: &str = "_ao";
+
+/// Extension of the optional sidecar next to a texture, e.g. `textures/brick.material.ron`
+pub const SIDECAR_EXTENSION: &str = "material.ron";
+
+/// Overrides for the material built for a texture, read from its `.material.ron` sidecar.
+/// Everything left out falls back to `MapLoaderSettings::material` and the suffix maps.
+/// Texture paths are relative to the sidecar.
+///
+/// ```ron
+/// (
+///     normal_map: Some("brick_n.png"),
+///     perceptual_roughness: Some(0.8),
+///     alpha_mode: Some(Mask(0.5)),
+///     double_sided: Some(true),
+/// )
+/// ```
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialSidecar {
+    /// sRGB, multiplied with the base color texture
+    pub base_color: Option<[f32; 4]>,
+    /// Replaces the map's texture as the base color texture
+    pub base_color_texture: Option<String>,
+    pub normal_map: Option<String>,
+    pub metallic_roughness_texture: Option<String>,
+    /// Linear RGB, can go above 1 for bloom
+    pub emissive: Option<[f32; 3]>,
+    pub emissive_texture: Option<String>,
+    pub occlusion_texture: Option<String>,
+    pub perceptual_roughness: Option<f32>,
+    pub metallic: Option<f32>,
+    pub reflectance: Option<f32>,
+    pub alpha_mode: Option<SidecarAlphaMode>,
+    pub double_sided: Option<bool>,
+    pub unlit: Option<bool>,
+}
+
+impl MaterialSidecar {
+    /// Applies the non texture properties
+    pub fn apply(&self, material: &mut StandardMaterial) {
+        if let Some([r, g, b, a]) = self.base_color {
+            material.base_color = Color::srgba(r, g, b, a);
+        }
+        if let Some([r, g, b]) = self.emissive {
+            material.emissive = LinearRgba::rgb(r, g, b);
+        }
+        if let Some(perceptual_roughness) = self.perceptual_roughness {
+            material.perceptual_roughness = perceptual_roughness;
+        }
+        if let Some(metallic) = self.metallic {
+            material.metallic = metallic;
+        }
+        if let Some(reflectance) = self.reflectance {
+            material.reflectance = reflectance;
+        }
+        if let Some(alpha_mode) = self.alpha_mode {
+            material.alpha_mode = alpha_mode.into();
+        }
+        if let Some(double_sided) = self.double_sided {
+            material.double_sided = double_sided;
+            // back faces need to be drawn for double sided materials
+            material.cull_mode = if double_sided {
+                None
+            } else {
+                Some(bevy::render::render_resource::Face::Back)
+            };
+        }
+        if let Some(unlit) = self.unlit {
+            material.unlit = unlit;
+        }
+    }
+}
+
+/// Serializable mirror of Bevy's `AlphaMode`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SidecarAlphaMode {
+    Opaque,
+    Mask(f32),
+    Blend,
+    Premultiplied,
+    Add,
+    Multiply,
+}
+
+impl From<SidecarAlphaMode> for AlphaMode {
+    fn from(alpha_mode: SidecarAlphaMode) -> Self {
+        match alpha_mode {
+            SidecarAlphaMode::Opaque => AlphaMode::Opaque,
+            SidecarAlphaMode::Mask(cutoff) => AlphaMode::Mask(cutoff),
+            SidecarAlphaMode::Blend => AlphaMode::Blend,
+            SidecarAlphaMode::Premultiplied => AlphaMode::Premultiplied,
+            SidecarAlphaMode::Add => AlphaMode::Add,
+            SidecarAlphaMode::Multiply => AlphaMode::Multiply,
+        }
+    }
+}
+
+/// A texture of a map, as handed to a [`MapMaterialFactory`]
+pub struct MapTexture<'a> {
+    pub name: &'a str,
+    pub image: &'a Handle<Image>,
+    /// The material the loader built, including sidecar and suffix maps
+    pub standard_material: &'a Handle<StandardMaterial>,
+    pub size: (u32, u32),
+}
+
+/// Makes the material of the map faces using a texture. Called once per texture of each map,
+/// the returned handle is shared by every mesh using that texture. Returning `None` skips those meshes.
+///
+/// ```ignore
+/// #[derive(Resource, Clone)]
+/// struct ToonMaterials;
+///
+/// impl MapMaterialFactory for ToonMaterials {
+///     type Material = ToonMaterial;
+///
+///     fn create_material(
+///         &self,
+///         texture: &MapTexture,
+///         materials: &mut Assets<ToonMaterial>,
+///     ) -> Option<Handle<ToonMaterial>> {
+///         Some(materials.add(ToonMaterial {
+///             texture: texture.image.clone(),
+///             bands: 4,
+///         }))
+///     }
+/// }
+///
+/// app.add_plugins(MapMaterialPlugin::new(ToonMaterials));
+/// ```
+pub trait MapMaterialFactory: Resource {
+    type Material: Material;
+
+    fn create_material(
+        &self,
+        texture: &MapTexture,
+        materials: &mut Assets<Self::Material>,
+    ) -> Option<Handle<Self::Material>>;
+}
+
+/// The default, uses the `StandardMaterial` made by the loader
+#[derive(Resource, Default)]
+pub struct StandardMapMaterial;
+
+impl MapMaterialFactory for StandardMapMaterial {
+    type Material = StandardMaterial;
+
+    fn create_material(
+        &self,
+        texture: &MapTexture,
+        _materials: &mut Assets<StandardMaterial>,
+    ) -> Option<Handle<StandardMaterial>> {
+        Some(texture.standard_material.clone())
+    }
+}
+
+/// Present when a [`MapMaterialPlugin`] replaced the default `StandardMaterial` faces
+#[derive(Resource)]
+pub struct CustomMapMaterial;
+
+/// Spawns map faces with the material made by `F` instead of `StandardMaterial`.
+/// Add it alongside `MapAssetLoaderPlugin`, `F::Material` still needs its own `MaterialPlugin`.
+/// Only one can be added, whatever its factory, since each would spawn every mesh again.
+pub struct MapMaterialPlugin<F: MapMaterialFactory + Clone>(pub F);
+
+impl<F: MapMaterialFactory + Clone> MapMaterialPlugin<F> {
+    pub fn new(factory: F) -> Self {
+        Self(factory)
+    }
+}
+
+impl<F: MapMaterialFactory + Clone> Plugin for MapMaterialPlugin<F> {
+    // the same name for every factory, so bevy refuses a second one
+    fn name(&self) -> &str {
+        "qevy::material::MapMaterialPlugin"
+    }
+
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.0.clone())
+            .insert_resource(CustomMapMaterial)
+            .add_systems(
+                PreUpdate,
+                mesh_spawn_system::<F>.after(crate::load::handle_loaded_map_system),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Resource, Clone)]
+    struct OtherMaterial;
+
+    impl MapMaterialFactory for OtherMaterial {
+        type Material = StandardMaterial;
+
+        fn create_material(
+            &self,
+            _texture: &MapTexture,
+            _materials: &mut Assets<StandardMaterial>,
+        ) -> Option<Handle<StandardMaterial>> {
+            None
+        }
+    }
+
+    #[derive(Resource, Clone)]
+    struct YetAnotherMaterial;
+
+    impl MapMaterialFactory for YetAnotherMaterial {
+        type Material = StandardMaterial;
+
+        fn create_material(
+            &self,
+            _texture: &MapTexture,
+            _materials: &mut Assets<StandardMaterial>,
+        ) -> Option<Handle<StandardMaterial>> {
+            None
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "already added")]
+    fn only_one_material_factory_can_be_added() {
+        App::new().add_plugins((
+            MapMaterialPlugin::new(OtherMaterial),
+            MapMaterialPlugin::new(YetAnotherMaterial),
+        ));
+    }
+}
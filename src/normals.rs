@@ -0,0 +1,154 @@
+use shambler::face::{FaceId, FacePlanes, FaceVertices};
+use shambler::Vector3;
+use std::collections::{BTreeMap, HashMap};
+
+/// ericw-tools' default for `_phong_angle`
+pub const DEFAULT_PHONG_ANGLE: f32 = 89.0;
+
+/// Vertices closer than this (in map units) are considered shared
+const VERTEX_WELD_DISTANCE: f32 = 0.1;
+
+/// Smooths normals across `faces` wherever they share a vertex, like ericw-tools' `_phong`.
+/// Faces only smooth into each other when the angle between them is at most `angle` degrees,
+/// so hard edges are kept. Each face contributes according to its area.
+pub fn phong_normals(
+    faces: &[FaceId],
+    face_vertices: &FaceVertices,
+    face_planes: &FacePlanes,
+    angle: f32,
+) -> BTreeMap<FaceId, Vec<Vector3>> {
+    let min_dot = angle.to_radians().cos();
+    let weld = |vertex: &Vector3| {
+        (
+            (vertex.x / VERTEX_WELD_DISTANCE).round() as i64,
+            (vertex.y / VERTEX_WELD_DISTANCE).round() as i64,
+            (vertex.z / VERTEX_WELD_DISTANCE).round() as i64,
+        )
+    };
+
+    // every face touching each vertex, along with its area weighted normal
+    let mut vertex_faces: HashMap<(i64, i64, i64), Vec<(Vector3, Vector3)>> = HashMap::new();
+    for face_id in faces {
+        let (Some(vertices), Some(plane)) = (face_vertices.get(face_id), face_planes.get(face_id))
+        else {
+            continue;
+        };
+
+        let normal = *plane.normal();
+        let weighted = normal * polygon_area(vertices);
+        for vertex in vertices {
+            vertex_faces
+                .entry(weld(vertex))
+                .or_default()
+                .push((normal, weighted));
+        }
+    }
+
+    let mut normals = BTreeMap::new();
+    for face_id in faces {
+        let (Some(vertices), Some(plane)) = (face_vertices.get(face_id), face_planes.get(face_id))
+        else {
+            continue;
+        };
+
+        let face_normal = *plane.normal();
+        normals.insert(
+            *face_id,
+            vertices
+                .iter()
+                .map(|vertex| {
+                    let sum = vertex_faces[&weld(vertex)]
+                        .iter()
+                        .filter(|(normal, _)| normal.dot(&face_normal) >= min_dot)
+                        .fold(Vector3::zeros(), |sum, (_, weighted)| sum + weighted);
+                    sum.try_normalize(f32::EPSILON).unwrap_or(face_normal)
+                })
+                .collect(),
+        );
+    }
+    normals
+}
+
+fn polygon_area(vertices: &[Vector3]) -> f32 {
+    let mut cross = Vector3::zeros();
+    for i in 1..vertices.len().saturating_sub(1) {
+        cross += (vertices[i] - vertices[0]).cross(&(vertices[i + 1] - vertices[0]));
+    }
+    cross.norm() * 0.5
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shambler::Plane3d;
+
+    /// A unit floor at z = 0 and a unit wall going down from its x = 1 edge, moved along x by `wall_offset`
+    fn floor_and_wall(wall_offset: f32) -> (Vec<FaceId>, FaceVertices, FacePlanes) {
+        let x = 1.0 + wall_offset;
+        let floor = (
+            Vector3::z(),
+            vec![
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(1.0, 0.0, 0.0),
+                Vector3::new(1.0, 1.0, 0.0),
+                Vector3::new(0.0, 1.0, 0.0),
+            ],
+        );
+        let wall = (
+            Vector3::x(),
+            vec![
+                Vector3::new(x, 0.0, 0.0),
+                Vector3::new(x, 1.0, 0.0),
+                Vector3::new(x, 1.0, -1.0),
+                Vector3::new(x, 0.0, -1.0),
+            ],
+        );
+        let faces = [floor, wall];
+        (
+            (0..faces.len()).map(FaceId).collect(),
+            faces
+                .iter()
+                .enumerate()
+                .map(|(i, (_, vertices))| (FaceId(i), vertices.clone()))
+                .collect(),
+            faces
+                .iter()
+                .enumerate()
+                .map(|(i, (normal, vertices))| {
+                    let d = normal.dot(&vertices[0]);
+                    (FaceId(i), Plane3d { n: *normal, d })
+                })
+                .collect(),
+        )
+    }
+
+    /// The normal of the floor's vertex on the shared edge
+    fn edge_normal(wall_offset: f32, angle: f32) -> Vector3 {
+        let (faces, vertices, planes) = floor_and_wall(wall_offset);
+        phong_normals(&faces, &vertices, &planes, angle)[&FaceId(0)][1]
+    }
+
+    #[test]
+    fn keeps_edges_sharper_than_the_angle() {
+        assert_eq!(edge_normal(0.0, DEFAULT_PHONG_ANGLE), Vector3::z());
+        // the corner away from the wall is only on the floor either way
+        let (faces, vertices, planes) = floor_and_wall(0.0);
+        assert_eq!(
+            phong_normals(&faces, &vertices, &planes, 91.0)[&FaceId(0)][0],
+            Vector3::z()
+        );
+    }
+
+    #[test]
+    fn smooths_edges_within_the_angle() {
+        let normal = edge_normal(0.0, 91.0);
+        assert!((normal - Vector3::new(1.0, 0.0, 1.0).normalize()).norm() < 1e-5);
+    }
+
+    #[test]
+    fn welds_vertices_within_the_tolerance() {
+        let welded = edge_normal(VERTEX_WELD_DISTANCE * 0.3, 91.0);
+        assert!((welded - Vector3::new(1.0, 0.0, 1.0).normalize()).norm() < 1e-5);
+        assert_eq!(edge_normal(VERTEX_WELD_DISTANCE * 5.0, 91.0), Vector3::z());
+    }
+}
@@ -0,0 +1,61 @@
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use std::time::Duration;
+
+/// A `path_corner` of a map
+#[derive(Debug, Clone)]
+pub struct PathCorner {
+    pub name: String,
+    /// the `Map` entity the corner belongs to
+    pub map: Entity,
+    /// the corner's origin, relative to its map
+    pub position: Vec3,
+    /// the corner its `target` names
+    pub next: Option<Entity>,
+    /// how long trains stop at the corner, `None` (-1) stops them until they're activated again
+    pub wait: Option<Duration>,
+    /// fired when a train gets to the corner
+    pub pathtarget: Option<String>,
+}
+
+/// Every `path_corner` of the loaded maps, linked through their target and targetname when the map is built.
+/// Used by `func_train`, and by games for anything following a path like patrolling NPCs.
+#[derive(Resource, Default)]
+pub struct PathGraph {
+    corners: HashMap<Entity, PathCorner>,
+    names: HashMap<(Entity, String), Entity>,
+}
+
+impl PathGraph {
+    pub fn get(&self, corner: Entity) -> Option<&PathCorner> {
+        self.corners.get(&corner)
+    }
+
+    /// The corner of a map with the given targetname
+    pub fn find(&self, map: Entity, name: &str) -> Option<Entity> {
+        self.names.get(&(map, name.to_string())).copied()
+    }
+
+    /// The corners from `start` on, following their targets until the path ends or loops back
+    pub fn path(&self, start: Entity) -> impl Iterator<Item = Entity> + '_ {
+        let mut visited = HashSet::new();
+        std::iter::successors(Some(start), |corner| self.corners.get(corner)?.next)
+            .take_while(move |corner| self.corners.contains_key(corner) && visited.insert(*corner))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &PathCorner)> {
+        self.corners
+            .iter()
+            .map(|(entity, corner)| (*entity, corner))
+    }
+
+    /// Replaces the corners of a map, when it's built again
+    pub(crate) fn insert_map(&mut self, map: Entity, corners: Vec<(Entity, PathCorner)>) {
+        self.corners.retain(|_, corner| corner.map != map);
+        self.names.retain(|(corner_map, _), _| *corner_map != map);
+        for (entity, corner) in corners {
+            self.names.insert((map, corner.name.clone()), entity);
+            self.corners.insert(entity, corner);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use bevy::image::{ImageAddressMode, ImageFilterMode, ImageSamplerDescriptor};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Settings for loading a map, set them in the map's `.meta` file or with `AssetServer::load_with_settings`:
+///
+/// ```ignore
+/// asset_server.load_with_settings("example.map", |settings: &mut MapLoaderSettings| {
+///     settings.texture_filtering = TextureFiltering::Linear;
+/// });
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MapLoaderSettings {
+    /// Directories (relative to the assets folder) searched for textures, in order
+    pub texture_roots: Vec<String>,
+    /// File extensions tried for each texture, in order. The matching Bevy image feature needs to be enabled
+    pub texture_extensions: Vec<String>,
+    pub texture_filtering: TextureFiltering,
+    pub material: MaterialDefaults,
+    /// Wads searched for textures that aren't found as loose files, after the ones listed in worldspawn's `wad` key
+    pub wads: Vec<String>,
+    /// Directories searched for the wads listed in worldspawn, since editors often write absolute paths there
+    pub wad_roots: Vec<String>,
+    /// Palette used for Quake (WAD2) textures when the wad doesn't have its own
+    pub palette: String,
+    /// How the faces of the static world brushes are grouped into meshes
+    pub batching: MeshBatching,
+    /// Classnames of the brush entities that make up the static world. Their faces are the ones
+    /// batched and culled, each brush still gets its own collider
+    pub world_classnames: Vec<String>,
+    /// Skips meshing world faces that are covered by another world brush, see `culling::hidden_faces`
+    pub remove_hidden_faces: bool,
+}
+
+impl Default for MapLoaderSettings {
+    fn default() -> Self {
+        Self {
+            texture_roots: vec!["textures".into()],
+            texture_extensions: vec![
+                "png".into(),
+                "jpg".into(),
+                "jpeg".into(),
+                "tga".into(),
+                "ktx2".into(),
+            ],
+            texture_filtering: TextureFiltering::default(),
+            material: MaterialDefaults::default(),
+            wads: Vec::new(),
+            wad_roots: vec!["".into(), "wads".into()],
+            palette: "gfx/palette.lmp".into(),
+            batching: MeshBatching::default(),
+            world_classnames: vec!["worldspawn".into(), "func_group".into()],
+            remove_hidden_faces: false,
+        }
+    }
+}
+
+impl MapLoaderSettings {
+    /// Every path a texture could be at, in the order they should be tried
+    pub fn texture_paths(&self, texture_name: &str) -> Vec<String> {
+        let mut paths = Vec::new();
+        for root in self.texture_roots.iter() {
+            for extension in self.texture_extensions.iter() {
+                paths.push(join_asset_path(
+                    root,
+                    &format!("{}.{}", texture_name, extension),
+                ));
+            }
+        }
+        paths
+    }
+
+    /// Every path a wad from worldspawn's `wad` key could be at, in the order they should be tried
+    pub fn wad_paths(&self, wad: &str) -> Vec<String> {
+        let wad = wad.trim().replace('\\', "/");
+        let file_name = wad.rsplit('/').next().unwrap_or(&wad);
+        let is_absolute = wad.starts_with('/') || wad.contains(':');
+
+        let mut paths = Vec::new();
+        if !is_absolute {
+            paths.push(wad.clone());
+        }
+        for root in self.wad_roots.iter() {
+            let path = join_asset_path(root, file_name);
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        paths
+    }
+}
+
+/// Joins a directory relative to the assets folder with a file name, an empty directory being the assets folder itself
+pub(crate) fn join_asset_path(directory: &str, file: &str) -> String {
+    let directory = directory.trim_end_matches('/');
+    if directory.is_empty() {
+        file.to_string()
+    } else {
+        format!("{}/{}", directory, file)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextureFiltering {
+    /// Crisp pixels, the classic software rendered look
+    #[default]
+    Nearest,
+    Linear,
+}
+
+impl TextureFiltering {
+    pub fn sampler_descriptor(&self) -> ImageSamplerDescriptor {
+        let filter = match self {
+            Self::Nearest => ImageFilterMode::Nearest,
+            Self::Linear => ImageFilterMode::Linear,
+        };
+
+        ImageSamplerDescriptor {
+            address_mode_u: ImageAddressMode::Repeat,
+            address_mode_v: ImageAddressMode::Repeat,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter: filter,
+            ..default()
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MeshBatching {
+    /// A mesh per texture of each brush, parented to the brush's collider
+    #[default]
+    PerBrush,
+    /// A single mesh per texture for the whole map
+    PerTexture,
+    /// A mesh per texture for each cell of a grid, so the parts out of view can be culled.
+    /// `size` is in map units
+    Chunked { size: f32 },
+}
+
+impl MeshBatching {
+    /// The batch a face centered at `center` (in map units) goes into, `None` if faces aren't batched
+    pub fn chunk(&self, center: Vec3) -> Option<IVec3> {
+        match self {
+            Self::PerBrush => None,
+            Self::PerTexture => Some(IVec3::ZERO),
+            Self::Chunked { size } if *size > 0.0 => Some((center / *size).floor().as_ivec3()),
+            Self::Chunked { .. } => Some(IVec3::ZERO),
+        }
+    }
+}
+
+/// Parameters of the `StandardMaterial` created for each texture
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialDefaults {
+    pub perceptual_roughness: f32,
+    pub metallic: f32,
+    pub reflectance: f32,
+    pub unlit: bool,
+    /// Opacity of liquid textures (see `TextureBehavior::liquid`), which are also double sided so they can be seen from below
+    pub liquid_alpha: f32,
+}
+
+impl Default for MaterialDefaults {
+    fn default() -> Self {
+        Self {
+            perceptual_roughness: 0.55,
+            metallic: 0.5,
+            reflectance: 0.5,
+            unlit: false,
+            liquid_alpha: 0.6,
+        }
+    }
+}
+
+impl MaterialDefaults {
+    pub fn to_material(&self, base_color_texture: Handle<Image>) -> StandardMaterial {
+        StandardMaterial {
+            base_color_texture: Some(base_color_texture),
+            perceptual_roughness: self.perceptual_roughness,
+            metallic: self.metallic,
+            reflectance: self.reflectance,
+            unlit: self.unlit,
+            ..default()
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use crate::components::*;
+
+/// The entities of each `targetname`, kept up to date as `TriggerTarget`s are added and removed.
+/// Several entities can share a targetname, they're all fired together.
+/// Names are kept per map, so the entities of a map only fire the ones of the same map.
+#[derive(Resource, Default)]
+pub struct TargetNames {
+    entities: HashMap<String, BTreeMap<Option<Entity>, BTreeSet<Entity>>>,
+}
+
+impl TargetNames {
+    /// The entities named `target_name` in `map`, in a stable order.
+    /// Without a map, e.g. for triggers spawned by the game, the entities named so in every map
+    pub fn get(&self, map: Option<Entity>, target_name: &str) -> impl Iterator<Item = Entity> + '_ {
+        self.entities
+            .get(target_name)
+            .into_iter()
+            .flat_map(move |maps| {
+                maps.iter()
+                    .filter(move |(name_map, _)| map.is_none() || **name_map == map)
+                    .flat_map(|(_, entities)| entities.iter().copied())
+            })
+    }
+}
+
+/// Finds the `Map` an entity belongs to, going up its parents
+#[derive(SystemParam)]
+pub struct MapOf<'w, 's> {
+    parents: Query<'w, 's, &'static Parent>,
+    maps: Query<'w, 's, (), With<Map>>,
+}
+
+impl MapOf<'_, '_> {
+    pub fn get(&self, entity: Entity) -> Option<Entity> {
+        std::iter::successors(Some(entity), |entity| {
+            self.parents.get(*entity).ok().map(Parent::get)
+        })
+        .find(|entity| self.maps.contains(*entity))
+    }
+}
+
+/// How an entity fires its targets, from its `killtarget`, `delay` and `message` properties
+#[derive(Component, Default, Clone)]
+pub struct UseTargets {
+    /// the entities named this are despawned when firing
+    pub killtarget: Option<String>,
+    /// waits this long after being triggered before firing
+    pub delay: Option<Duration>,
+    /// sent to the activator in a `TargetMessage` when firing
+    pub message: Option<String>,
+}
+
+impl UseTargets {
+    pub fn from_properties(props: &MapEntityProperties) -> Self {
+        let delay = props.get_property_as_f32("delay", 0.0);
+        Self {
+            killtarget: props
+                .get_property_as_string("killtarget", None)
+                .filter(|killtarget| !killtarget.is_empty()),
+            delay: (delay > 0.0).then(|| Duration::from_secs_f32(delay)),
+            message: props
+                .get_property_as_string("message", None)
+                .filter(|message| !message.is_empty()),
+        }
+    }
+}
+
+/// Sent for, and triggered on, every entity named by the target of a fired trigger.
+///
+/// ```ignore
+/// commands.entity(door).observe(|trigger: Trigger<Activated>, mut doors: Query<&mut Door>| {
+///     // open the door
+/// });
+/// ```
+#[derive(Event, Debug, Clone, Copy)]
+pub struct Activated {
+    /// whatever set off the trigger, e.g. the player
+    pub activator: Entity,
+    pub target_entity: Entity,
+}
+
+/// Sent when firing a trigger with a `message`, for showing it to the activator
+#[derive(Event, Debug)]
+pub struct TargetMessage {
+    pub activator: Entity,
+    pub message: String,
+}
+
+/// Triggers waiting out their `delay`
+#[derive(Resource, Default)]
+pub struct DelayedTargets {
+    pending: Vec<(Timer, PendingTargets)>,
+}
+
+struct PendingTargets {
+    activator: Entity,
+    /// the map of the trigger, whose entities it fires
+    map: Option<Entity>,
+    target: String,
+    use_targets: UseTargets,
+}
+
+/// What a `TriggeredEvent` fires: the `UseTargets` of its trigger, in the trigger's map
+#[derive(SystemParam)]
+pub struct TriggeredTargets<'w, 's> {
+    map_of: MapOf<'w, 's>,
+    use_targets: Query<'w, 's, &'static UseTargets>,
+}
+
+impl TriggeredTargets<'_, '_> {
+    fn pending(&self, event: &TriggeredEvent) -> PendingTargets {
+        PendingTargets {
+            activator: event.triggered_by,
+            map: self.map_of.get(event.trigger),
+            target: event.target.clone(),
+            use_targets: self
+                .use_targets
+                .get(event.trigger)
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// The events sent when targets are fired
+#[derive(SystemParam)]
+pub struct TargetEvents<'w> {
+    activated: EventWriter<'w, Activated>,
+    message: EventWriter<'w, TargetMessage>,
+}
+
+pub(crate) fn add_target_name_observer(
+    trigger: Trigger<OnAdd, TriggerTarget>,
+    targets: Query<&TriggerTarget>,
+    mut target_names: ResMut<TargetNames>,
+) {
+    if let Ok(target) = targets.get(trigger.entity()) {
+        target_names
+            .entities
+            .entry(target.target_name.clone())
+            .or_default()
+            .entry(target.map)
+            .or_default()
+            .insert(trigger.entity());
+    }
+}
+
+pub(crate) fn remove_target_name_observer(
+    trigger: Trigger<OnRemove, TriggerTarget>,
+    targets: Query<&TriggerTarget>,
+    mut target_names: ResMut<TargetNames>,
+) {
+    let Ok(target) = targets.get(trigger.entity()) else {
+        return;
+    };
+    let Some(maps) = target_names.entities.get_mut(&target.target_name) else {
+        return;
+    };
+    if let Some(entities) = maps.get_mut(&target.map) {
+        entities.remove(&trigger.entity());
+        if entities.is_empty() {
+            maps.remove(&target.map);
+        }
+    }
+    if maps.is_empty() {
+        target_names.entities.remove(&target.target_name);
+    }
+}
+
+/// Fires the targets of every `TriggeredEvent`, once the trigger's `delay` has passed:
+/// sends a `TargetMessage` for its `message`, despawns its `killtarget` entities,
+/// then sends and triggers `Activated` for each entity named by its target
+pub fn use_targets_system(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut triggered_events: EventReader<TriggeredEvent>,
+    mut delayed: ResMut<DelayedTargets>,
+    target_names: Res<TargetNames>,
+    triggered: TriggeredTargets,
+    mut events: TargetEvents,
+) {
+    for (timer, _) in delayed.pending.iter_mut() {
+        timer.tick(time.delta());
+    }
+    let (finished, waiting) = std::mem::take(&mut delayed.pending)
+        .into_iter()
+        .partition::<Vec<_>, _>(|(timer, _)| timer.finished());
+    delayed.pending = waiting;
+    let mut ready = finished
+        .into_iter()
+        .map(|(_, pending)| pending)
+        .collect::<Vec<_>>();
+
+    for event in triggered_events.read() {
+        let pending = triggered.pending(event);
+        match pending.use_targets.delay {
+            Some(delay) => delayed
+                .pending
+                .push((Timer::new(delay, TimerMode::Once), pending)),
+            None => ready.push(pending),
+        }
+    }
+
+    for pending in ready {
+        if let Some(message) = pending.use_targets.message {
+            events.message.send(TargetMessage {
+                activator: pending.activator,
+                message,
+            });
+        }
+        if let Some(killtarget) = pending.use_targets.killtarget {
+            for entity in target_names.get(pending.map, &killtarget) {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+        for target_entity in target_names.get(pending.map, &pending.target) {
+            let activated = Activated {
+                activator: pending.activator,
+                target_entity,
+            };
+            events.activated.send(activated);
+            commands.trigger_targets(activated, target_entity);
+        }
+    }
+}
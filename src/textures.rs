@@ -0,0 +1,188 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::components::LiquidKind;
+
+/// The collision layer of `playerclip` brushes, leave it out of the filters of what shouldn't be blocked by them
+pub const PLAYER_CLIP_LAYER: u32 = 1 << 1;
+
+/// What a brush face does depending on its texture, set on the `MapAssetLoaderPlugin`.
+/// The first matching rule wins, textures without one are rendered and collide:
+///
+/// ```ignore
+/// qevy::MapAssetLoaderPlugin {
+///     texture_rules: TextureRules::default()
+///         .with_rule(TexturePattern::Prefix("fx_".into()), TextureBehavior::HIDDEN),
+///     ..default()
+/// }
+/// ```
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+pub struct TextureRules {
+    pub rules: Vec<TextureRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TextureRule {
+    pub pattern: TexturePattern,
+    pub behavior: TextureBehavior,
+}
+
+/// Matched against texture names ignoring case, with or without their directory (`clip` matches `common/clip`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TexturePattern {
+    Name(String),
+    Prefix(String),
+}
+
+/// The brush of a face only gets a collider if all of its faces collide, taking the first collision layers found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextureBehavior {
+    /// a mesh is built for the face
+    pub render: bool,
+    pub collide: bool,
+    /// the memberships of the brush's collider, as `CollisionGroups` with rapier and `CollisionLayers` with avian
+    pub collision_layers: Option<u32>,
+    /// the face looks into the sky, its brush's collider gets a `SkyPortal`
+    pub sky: bool,
+    /// the face's brush is a `LiquidVolume` instead of being solid
+    pub liquid: Option<LiquidKind>,
+    /// a brush of only these faces marks the point its entity turns about, and isn't built
+    pub origin: bool,
+    /// the face can't be seen through, so world brushes of only these faces hide the faces inside them
+    pub opaque: bool,
+}
+
+impl TextureBehavior {
+    pub const VISIBLE: Self = Self {
+        render: true,
+        collide: true,
+        collision_layers: None,
+        sky: false,
+        liquid: None,
+        origin: false,
+        opaque: true,
+    };
+    /// not rendered, but still solid like `clip` and `skip`
+    pub const HIDDEN: Self = Self {
+        render: false,
+        opaque: false,
+        ..Self::VISIBLE
+    };
+    /// neither rendered nor solid, like `hint`
+    pub const IGNORED: Self = Self {
+        collide: false,
+        ..Self::HIDDEN
+    };
+    /// rendered and solid, but seen through like masked (`{`) textures
+    pub const TRANSPARENT: Self = Self {
+        opaque: false,
+        ..Self::VISIBLE
+    };
+}
+
+impl Default for TextureBehavior {
+    fn default() -> Self {
+        Self::VISIBLE
+    }
+}
+
+impl TexturePattern {
+    pub fn matches(&self, texture_name: &str) -> bool {
+        let texture_name = texture_name.to_lowercase();
+        let file_name = texture_name.rsplit('/').next().unwrap_or_default();
+        match self {
+            Self::Name(name) => {
+                let name = name.to_lowercase();
+                texture_name == name || file_name == name
+            }
+            Self::Prefix(prefix) => {
+                let prefix = prefix.to_lowercase();
+                texture_name.starts_with(&prefix) || file_name.starts_with(&prefix)
+            }
+        }
+    }
+}
+
+impl TextureRules {
+    /// No rules at all, every texture is rendered and collides
+    pub fn empty() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    /// Adds a rule that wins over the ones already there
+    pub fn with_rule(mut self, pattern: TexturePattern, behavior: TextureBehavior) -> Self {
+        self.rules.insert(0, TextureRule { pattern, behavior });
+        self
+    }
+
+    pub fn get(&self, texture_name: &str) -> TextureBehavior {
+        self.rules
+            .iter()
+            .find(|rule| rule.pattern.matches(texture_name))
+            .map(|rule| rule.behavior)
+            .unwrap_or_default()
+    }
+}
+
+impl Default for TextureRules {
+    /// The editor and compiler textures of Quake, Half-Life and Quake 3 style texture sets,
+    /// skies drawn by the worldspawn's `Skybox` instead, Quake's liquids (`*`) along with Half-Life's water (`!`)
+    /// and masked textures (`{`)
+    fn default() -> Self {
+        let name = |name: &str| TexturePattern::Name(name.into());
+        let liquid = |prefix: &str, kind| {
+            (
+                TexturePattern::Prefix(prefix.into()),
+                TextureBehavior {
+                    liquid: Some(kind),
+                    ..TextureBehavior::TRANSPARENT
+                },
+            )
+        };
+        let rules = [
+            (name("trigger"), TextureBehavior::HIDDEN),
+            (name("clip"), TextureBehavior::HIDDEN),
+            (
+                name("playerclip"),
+                TextureBehavior {
+                    collision_layers: Some(PLAYER_CLIP_LAYER),
+                    ..TextureBehavior::HIDDEN
+                },
+            ),
+            (name("skip"), TextureBehavior::HIDDEN),
+            (name("nodraw"), TextureBehavior::HIDDEN),
+            (name("caulk"), TextureBehavior::HIDDEN),
+            (name("hint"), TextureBehavior::IGNORED),
+            (name("__TB_empty"), TextureBehavior::HIDDEN),
+            (
+                name("origin"),
+                TextureBehavior {
+                    origin: true,
+                    ..TextureBehavior::HIDDEN
+                },
+            ),
+            (
+                TexturePattern::Prefix("sky".into()),
+                TextureBehavior {
+                    sky: true,
+                    ..TextureBehavior::HIDDEN
+                },
+            ),
+            liquid("*slime", LiquidKind::Slime),
+            liquid("*lava", LiquidKind::Lava),
+            liquid("*", LiquidKind::Water),
+            liquid("!", LiquidKind::Water),
+            (
+                TexturePattern::Prefix("{".into()),
+                TextureBehavior::TRANSPARENT,
+            ),
+        ];
+        Self {
+            rules: rules
+                .into_iter()
+                .map(|(pattern, behavior)| TextureRule { pattern, behavior })
+                .collect(),
+        }
+    }
+}
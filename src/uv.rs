@@ -0,0 +1,275 @@
+use bevy::prelude::*;
+use shalrath::repr::{TextureOffset, TexturePlane};
+use shambler::face::{FaceId, FacePlanes, FaceVertices};
+use shambler::{GeoMap, Vector2, Vector3};
+use std::collections::BTreeMap;
+
+/// Size used for textures we couldn't load (e.g. headless or missing files)
+pub const DEFAULT_TEXTURE_SIZE: (u32, u32) = (256, 256);
+
+/// The texture coordinate format used by the faces of a map.
+/// TrenchBroom writes this as the `// Format:` header, but the header is only a comment,
+/// so we detect it from the faces themselves.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MapFormat {
+    /// Quake style `offset_u offset_v angle scale_x scale_y` faces
+    #[default]
+    Standard,
+    /// Valve 220 style `[ ux uy uz offset ] [ vx vy vz offset ] angle scale_x scale_y` faces
+    Valve,
+    /// Both kinds of faces are present, e.g. brushes pasted in from another map
+    Mixed,
+}
+
+impl MapFormat {
+    pub fn detect(geomap: &GeoMap) -> Self {
+        let mut standard = false;
+        let mut valve = false;
+        for offset in geomap.face_offsets.values() {
+            match offset {
+                TextureOffset::Standard { .. } => standard = true,
+                TextureOffset::Valve { .. } => valve = true,
+            }
+        }
+
+        match (standard, valve) {
+            (true, true) => Self::Mixed,
+            (false, true) => Self::Valve,
+            _ => Self::Standard,
+        }
+    }
+}
+
+/// Computes the UVs of every face, picking the projection based on each face's own format.
+pub fn face_uvs(
+    geomap: &GeoMap,
+    face_vertices: &FaceVertices,
+    face_planes: &FacePlanes,
+    texture_sizes: &BTreeMap<&str, (u32, u32)>,
+) -> BTreeMap<FaceId, Vec<Vec2>> {
+    let mut uvs = BTreeMap::new();
+    for face_id in geomap.faces.iter() {
+        let (Some(vertices), Some(plane)) = (face_vertices.get(face_id), face_planes.get(face_id))
+        else {
+            continue;
+        };
+
+        let texture_size = geomap
+            .face_textures
+            .get(face_id)
+            .and_then(|texture_id| geomap.textures.get(texture_id))
+            .and_then(|texture_name| texture_sizes.get(texture_name.as_str()))
+            .copied()
+            .unwrap_or(DEFAULT_TEXTURE_SIZE);
+        let texture_size = Vec2::new(texture_size.0 as f32, texture_size.1 as f32);
+        let offset = geomap.face_offsets[face_id];
+        let angle = geomap.face_angles[face_id];
+        let scale = geomap.face_scales[face_id];
+
+        uvs.insert(
+            *face_id,
+            vertices
+                .iter()
+                .map(|vertex| match offset {
+                    TextureOffset::Standard { u, v } => standard_uv(
+                        vertex,
+                        plane.normal(),
+                        Vec2::new(u, v),
+                        angle,
+                        &scale,
+                        texture_size,
+                    ),
+                    TextureOffset::Valve { u, v } => valve_uv(vertex, &u, &v, &scale, texture_size),
+                })
+                .collect(),
+        );
+    }
+    uvs
+}
+
+/// Quake's paraxial projection. The base axes and their order match qbsp's `baseaxis` table
+/// (and TrenchBroom's), so faces that are equally close to two axes pick the same one as the editor.
+pub fn standard_uv(
+    vertex: &Vector3,
+    normal: &Vector3,
+    offset: Vec2,
+    angle: f32,
+    scale: &Vector2,
+    texture_size: Vec2,
+) -> Vec2 {
+    const BASE_AXES: [([f32; 3], usize, usize); 6] = [
+        ([0.0, 0.0, 1.0], 0, 1),  // floor
+        ([0.0, 0.0, -1.0], 0, 1), // ceiling
+        ([1.0, 0.0, 0.0], 1, 2),  // west wall
+        ([-1.0, 0.0, 0.0], 1, 2), // east wall
+        ([0.0, 1.0, 0.0], 0, 2),  // south wall
+        ([0.0, -1.0, 0.0], 0, 2), // north wall
+    ];
+
+    let mut best = 0;
+    let mut best_dot = f32::MIN;
+    for (i, (axis, ..)) in BASE_AXES.iter().enumerate() {
+        let dot = normal.x * axis[0] + normal.y * axis[1] + normal.z * axis[2];
+        if dot > best_dot {
+            best = i;
+            best_dot = dot;
+        }
+    }
+    let (_, u_index, v_index) = BASE_AXES[best];
+    let projected = Vec2::new(vertex[u_index], -vertex[v_index]);
+
+    // snap the common angles so axis aligned textures don't pick up float error
+    let (sin, cos) = match angle.rem_euclid(360.0) {
+        0.0 => (0.0, 1.0),
+        90.0 => (1.0, 0.0),
+        180.0 => (0.0, -1.0),
+        270.0 => (-1.0, 0.0),
+        angle => angle.to_radians().sin_cos(),
+    };
+    let rotated = Vec2::new(
+        projected.x * cos - projected.y * sin,
+        projected.x * sin + projected.y * cos,
+    );
+
+    (rotated / non_zero_scale(scale) + offset) / texture_size
+}
+
+/// Valve 220 projection, the texture axes are stored on the face itself so rotated
+/// brushes keep their texture alignment (texture lock).
+pub fn valve_uv(
+    vertex: &Vector3,
+    u_axis: &TexturePlane,
+    v_axis: &TexturePlane,
+    scale: &Vector2,
+    texture_size: Vec2,
+) -> Vec2 {
+    let projected = Vec2::new(
+        vertex.x * u_axis.x + vertex.y * u_axis.y + vertex.z * u_axis.z,
+        vertex.x * v_axis.x + vertex.y * v_axis.y + vertex.z * v_axis.z,
+    );
+
+    (projected / non_zero_scale(scale) + Vec2::new(u_axis.d, v_axis.d)) / texture_size
+}
+
+/// qbsp treats a scale of 0 as 1
+fn non_zero_scale(scale: &Vector2) -> Vec2 {
+    Vec2::new(
+        if scale.x == 0.0 { 1.0 } else { scale.x },
+        if scale.y == 0.0 { 1.0 } else { scale.y },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texture_plane(x: f32, y: f32, z: f32, d: f32) -> TexturePlane {
+        TexturePlane { x, y, z, d }
+    }
+
+    fn assert_uv(uv: Vec2, expected: Vec2) {
+        assert!(uv.abs_diff_eq(expected, 1e-5), "{uv} != {expected}");
+    }
+
+    #[test]
+    fn standard_projects_floors_onto_xy() {
+        let uv = standard_uv(
+            &Vector3::new(32.0, 64.0, 0.0),
+            &Vector3::z(),
+            Vec2::ZERO,
+            0.0,
+            &Vector2::new(1.0, 1.0),
+            Vec2::splat(64.0),
+        );
+        assert_uv(uv, Vec2::new(0.5, -1.0));
+    }
+
+    #[test]
+    fn standard_rotates_then_scales_then_offsets() {
+        let uv = standard_uv(
+            &Vector3::new(32.0, 64.0, 0.0),
+            &Vector3::z(),
+            Vec2::splat(16.0),
+            90.0,
+            &Vector2::new(2.0, 2.0),
+            Vec2::splat(64.0),
+        );
+        assert_uv(uv, Vec2::new(0.75, 0.5));
+    }
+
+    #[test]
+    fn standard_breaks_ties_like_qbsp() {
+        // as close to the west wall as to the south one, qbsp's table picks the west wall's y/z axes
+        let uv = standard_uv(
+            &Vector3::new(10.0, 20.0, 30.0),
+            &Vector3::new(1.0, 1.0, 0.0).normalize(),
+            Vec2::ZERO,
+            0.0,
+            &Vector2::new(0.0, 0.0),
+            Vec2::ONE,
+        );
+        assert_uv(uv, Vec2::new(20.0, -30.0));
+    }
+
+    #[test]
+    fn valve_matches_standard_for_default_axes() {
+        let vertex = Vector3::new(32.0, 64.0, 0.0);
+        let scale = Vector2::new(0.5, 0.25);
+        let valve = valve_uv(
+            &vertex,
+            &texture_plane(1.0, 0.0, 0.0, 8.0),
+            &texture_plane(0.0, -1.0, 0.0, 4.0),
+            &scale,
+            Vec2::splat(64.0),
+        );
+        let standard = standard_uv(
+            &vertex,
+            &Vector3::z(),
+            Vec2::new(8.0, 4.0),
+            0.0,
+            &scale,
+            Vec2::splat(64.0),
+        );
+        assert_uv(valve, standard);
+    }
+
+    #[test]
+    fn valve_uses_the_face_axes() {
+        // a floor with its texture turned 45 degrees, stretched on u and squashed on v
+        let half = 0.5f32.sqrt();
+        let uv = valve_uv(
+            &Vector3::new(64.0, 32.0, 0.0),
+            &texture_plane(half, half, 0.0, 16.0),
+            &texture_plane(half, -half, 0.0, -8.0),
+            &Vector2::new(0.5, 2.0),
+            Vec2::new(128.0, 64.0),
+        );
+        assert_uv(uv, Vec2::new(1.185_660_2, 0.051_776_7));
+    }
+
+    #[test]
+    fn mixed_formats_map_projects_each_face_in_its_own_format() {
+        let map = crate::load::parse_map(include_str!("../assets/mixed_formats.map")).unwrap();
+        let geomap = GeoMap::new(map);
+        assert_eq!(MapFormat::detect(&geomap), MapFormat::Mixed);
+
+        // brushes 0-2 are Valve 220 and brushes 3-4 Standard, 6 faces each
+        let valve = geomap
+            .face_offsets
+            .values()
+            .filter(|offset| matches!(offset, TextureOffset::Valve { .. }))
+            .count();
+        assert_eq!((valve, geomap.face_offsets.len()), (18, 30));
+
+        let face_planes = shambler::face::face_planes(&geomap.face_planes);
+        let brush_hulls = shambler::brush::brush_hulls(&geomap.brush_faces, &face_planes);
+        let (face_vertices, _) =
+            shambler::face::face_vertices(&geomap.brush_faces, &face_planes, &brush_hulls);
+        let uvs = face_uvs(&geomap, &face_vertices, &face_planes, &BTreeMap::new());
+        assert_eq!(uvs.len(), 30);
+        for (face_id, uvs) in uvs.iter() {
+            assert_eq!(uvs.len(), face_vertices[face_id].len());
+            assert!(uvs.iter().all(|uv| uv.is_finite()));
+        }
+    }
+}
@@ -0,0 +1,508 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::image::ImageSampler;
+use bevy::prelude::*;
+use bevy::reflect::TypePath;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+use crate::settings::TextureFiltering;
+
+// https://www.gamers.org/dEngine/quake/spec/quake-spec34/qkspec_7.htm
+// https://twhl.info/wiki/page/Specification:_WAD3
+
+const DIRECTORY_ENTRY_SIZE: usize = 32;
+const LUMP_TYPE_PALETTE: u8 = 0x40;
+const LUMP_TYPE_WAD3_MIPTEX: u8 = 0x43;
+const LUMP_TYPE_WAD2_MIPTEX: u8 = 0x44;
+const MIPTEX_HEADER_SIZE: usize = 40;
+
+#[derive(Debug, Error)]
+pub enum WadError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("not a WAD2 or WAD3 file")]
+    InvalidMagic,
+    #[error("lump {0} goes past the end of the file")]
+    Truncated(String),
+    #[error("lump {0} is compressed, which isn't supported")]
+    Compressed(String),
+    #[error("texture {0} needs a palette, but none was found")]
+    MissingPalette(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WadVersion {
+    /// Quake, textures use the game's palette (`gfx/palette.lmp`)
+    Wad2,
+    /// Half-Life, every texture carries its own palette
+    Wad3,
+}
+
+/// 256 RGB colors, the format of Quake's `palette.lmp`
+#[derive(Debug, Clone)]
+pub struct Palette(pub [[u8; 3]; 256]);
+
+impl Palette {
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 256 * 3 {
+            return None;
+        }
+
+        let mut colors = [[0; 3]; 256];
+        for (i, color) in colors.iter_mut().enumerate() {
+            color.copy_from_slice(&bytes[i * 3..i * 3 + 3]);
+        }
+        Some(Self(colors))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct WadLump {
+    name: String,
+    offset: usize,
+    size: usize,
+    kind: u8,
+    compression: u8,
+}
+
+/// A parsed `.wad` file, textures are decoded on demand.
+pub struct Wad {
+    version: WadVersion,
+    bytes: Vec<u8>,
+    lumps: Vec<WadLump>,
+}
+
+impl Wad {
+    pub fn parse(bytes: Vec<u8>) -> Result<Self, WadError> {
+        let version = match bytes.get(0..4) {
+            Some(b"WAD2") => WadVersion::Wad2,
+            Some(b"WAD3") => WadVersion::Wad3,
+            _ => return Err(WadError::InvalidMagic),
+        };
+        let header = || WadError::Truncated("header".into());
+        let count = read_u32(&bytes, 4).ok_or_else(header)? as usize;
+        let directory = read_u32(&bytes, 8).ok_or_else(header)? as usize;
+
+        // the count comes from the file, don't trust it with more entries than the file can hold
+        let available = bytes.len().saturating_sub(directory) / DIRECTORY_ENTRY_SIZE;
+        if count > available {
+            return Err(WadError::Truncated("directory".into()));
+        }
+
+        let mut lumps = Vec::with_capacity(count);
+        for i in 0..count {
+            let entry = directory + i * DIRECTORY_ENTRY_SIZE;
+            let Some(entry) = bytes.get(entry..entry + DIRECTORY_ENTRY_SIZE) else {
+                return Err(WadError::Truncated("directory".into()));
+            };
+
+            lumps.push(WadLump {
+                name: read_name(&entry[16..32]),
+                offset: read_u32(entry, 0).unwrap() as usize,
+                size: read_u32(entry, 4).unwrap() as usize,
+                kind: entry[12],
+                compression: entry[13],
+            });
+        }
+
+        Ok(Self {
+            version,
+            bytes,
+            lumps,
+        })
+    }
+
+    pub fn version(&self) -> WadVersion {
+        self.version
+    }
+
+    /// Names of all the textures in the wad, as written in the file
+    pub fn texture_names(&self) -> impl Iterator<Item = &str> {
+        self.lumps
+            .iter()
+            .filter(|lump| is_miptex(lump.kind))
+            .map(|lump| lump.name.as_str())
+    }
+
+    /// The palette stored in the wad itself, some Quake wads ship one as a lump named `palette`
+    pub fn palette(&self) -> Option<Palette> {
+        let lump = self.lumps.iter().find(|lump| {
+            lump.kind == LUMP_TYPE_PALETTE || lump.name.eq_ignore_ascii_case("palette")
+        })?;
+        Palette::from_bytes(self.lump_bytes(lump).ok()?)
+    }
+
+    /// Decodes a texture to RGBA8, looked up case insensitively like Quake does.
+    /// WAD2 textures need `palette`, WAD3 textures use their own.
+    /// Textures starting with `{` are transparent where they use the last palette color.
+    pub fn decode_texture(
+        &self,
+        name: &str,
+        palette: Option<&Palette>,
+    ) -> Option<Result<WadTexture, WadError>> {
+        let lump = self
+            .lumps
+            .iter()
+            .find(|lump| is_miptex(lump.kind) && lump.name.eq_ignore_ascii_case(name))?;
+        Some(self.decode_miptex(lump, palette))
+    }
+
+    fn decode_miptex(
+        &self,
+        lump: &WadLump,
+        palette: Option<&Palette>,
+    ) -> Result<WadTexture, WadError> {
+        let truncated = || WadError::Truncated(lump.name.clone());
+        let data = self.lump_bytes(lump)?;
+        let width = read_u32(data, 16).ok_or_else(truncated)? as usize;
+        let height = read_u32(data, 20).ok_or_else(truncated)? as usize;
+        let mip0 = read_u32(data, 24).ok_or_else(truncated)? as usize;
+        let mip3 = read_u32(data, 36).ok_or_else(truncated)? as usize;
+        if data.len() < MIPTEX_HEADER_SIZE {
+            return Err(truncated());
+        }
+        // dimensions and offsets come from the file, bad ones mustn't overflow
+        let pixels = width.checked_mul(height).ok_or_else(truncated)?;
+        let indices = data
+            .get(mip0..mip0.checked_add(pixels).ok_or_else(truncated)?)
+            .ok_or_else(truncated)?;
+
+        let embedded_palette = match self.version {
+            WadVersion::Wad2 => None,
+            // the palette follows the smallest mip level, prefixed by its color count
+            WadVersion::Wad3 => {
+                let start = mip3.checked_add((width / 8) * (height / 8) + 2);
+                start
+                    .and_then(|start| data.get(start..))
+                    .and_then(Palette::from_bytes)
+            }
+        };
+        let palette = embedded_palette
+            .as_ref()
+            .or(palette)
+            .ok_or_else(|| WadError::MissingPalette(lump.name.clone()))?;
+
+        let transparent = lump.name.starts_with('{');
+        let mut rgba = Vec::with_capacity(pixels.checked_mul(4).ok_or_else(truncated)?);
+        for index in indices {
+            let [r, g, b] = palette.0[*index as usize];
+            if transparent && *index == 255 {
+                rgba.extend_from_slice(&[0, 0, 0, 0]);
+            } else {
+                rgba.extend_from_slice(&[r, g, b, 255]);
+            }
+        }
+
+        Ok(WadTexture {
+            name: lump.name.clone(),
+            width: width as u32,
+            height: height as u32,
+            rgba,
+        })
+    }
+
+    fn lump_bytes(&self, lump: &WadLump) -> Result<&[u8], WadError> {
+        if lump.compression != 0 {
+            return Err(WadError::Compressed(lump.name.clone()));
+        }
+        let truncated = || WadError::Truncated(lump.name.clone());
+        let end = lump.offset.checked_add(lump.size).ok_or_else(truncated)?;
+        self.bytes.get(lump.offset..end).ok_or_else(truncated)
+    }
+}
+
+/// A decoded mip texture
+pub struct WadTexture {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+impl WadTexture {
+    pub fn is_transparent(&self) -> bool {
+        self.name.starts_with('{')
+    }
+
+    pub fn into_image(self, filtering: TextureFiltering) -> Image {
+        let mut image = Image::new(
+            Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            self.rgba,
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::RENDER_WORLD,
+        );
+        image.sampler = ImageSampler::Descriptor(filtering.sampler_descriptor());
+        image
+    }
+}
+
+fn is_miptex(kind: u8) -> bool {
+    kind == LUMP_TYPE_WAD2_MIPTEX || kind == LUMP_TYPE_WAD3_MIPTEX
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn read_name(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// All the textures of a `.wad` file, each one is also available as a labeled
+/// `Image` asset, e.g. `textures.wad#textures/brick1`.
+#[derive(Debug, Asset, TypePath)]
+pub struct WadAsset {
+    pub textures: BTreeMap<String, Handle<Image>>,
+}
+
+impl WadAsset {
+    /// Looks up a texture case insensitively
+    pub fn get_texture(&self, name: &str) -> Option<&Handle<Image>> {
+        self.textures.get(&name.to_lowercase())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WadLoaderSettings {
+    /// Palette used for WAD2 textures when the wad doesn't have its own
+    pub palette: String,
+    pub texture_filtering: TextureFiltering,
+}
+
+impl Default for WadLoaderSettings {
+    fn default() -> Self {
+        Self {
+            palette: "gfx/palette.lmp".into(),
+            texture_filtering: TextureFiltering::default(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct WadAssetLoader;
+
+impl AssetLoader for WadAssetLoader {
+    type Asset = WadAsset;
+    type Settings = WadLoaderSettings;
+    type Error = WadError;
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        settings: &Self::Settings,
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let wad = Wad::parse(bytes)?;
+
+        let mut palette = wad.palette();
+        if palette.is_none() && wad.version() == WadVersion::Wad2 {
+            palette = load_palette(&settings.palette, load_context).await;
+        }
+
+        let mut textures = BTreeMap::new();
+        for name in wad.texture_names() {
+            match wad.decode_texture(name, palette.as_ref()) {
+                Some(Ok(texture)) => {
+                    let handle = load_context.add_loaded_labeled_asset(
+                        format!("textures/{}", name.to_lowercase()),
+                        LoadedAsset::from(texture.into_image(settings.texture_filtering)),
+                    );
+                    textures.insert(name.to_lowercase(), handle);
+                }
+                Some(Err(e)) => warn!("skipping wad texture {}: {}", name, e),
+                None => {}
+            }
+        }
+
+        Ok(WadAsset { textures })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["wad"]
+    }
+}
+
+pub(crate) async fn load_palette(
+    path: &str,
+    load_context: &mut LoadContext<'_>,
+) -> Option<Palette> {
+    match load_context.read_asset_bytes(path).await {
+        Ok(bytes) => Palette::from_bytes(&bytes),
+        Err(e) => {
+            warn!("could not load palette {}: {}", path, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A wad with the given `(name, kind, data)` lumps, the directory after them
+    fn wad(magic: &[u8; 4], lumps: &[(&str, u8, Vec<u8>)]) -> Vec<u8> {
+        let mut bytes = magic.to_vec();
+        bytes.extend((lumps.len() as u32).to_le_bytes());
+        bytes.extend([0; 4]);
+        let mut directory = Vec::new();
+        for (name, kind, data) in lumps {
+            directory.extend((bytes.len() as u32).to_le_bytes());
+            directory.extend((data.len() as u32).to_le_bytes());
+            directory.extend((data.len() as u32).to_le_bytes());
+            directory.extend([*kind, 0, 0, 0]);
+            let mut lump_name = [0; 16];
+            lump_name[..name.len()].copy_from_slice(name.as_bytes());
+            directory.extend(lump_name);
+            bytes.extend(data);
+        }
+        let directory_offset = (bytes.len() as u32).to_le_bytes();
+        bytes[8..12].copy_from_slice(&directory_offset);
+        bytes.extend(directory);
+        bytes
+    }
+
+    /// An 8x8 mip texture using the given palette indices, WAD3 ones followed by `palette`
+    fn miptex(name: &str, pixels: [u8; 64], palette: Option<&Palette>) -> Vec<u8> {
+        let mut data = vec![0; 16];
+        data[..name.len()].copy_from_slice(name.as_bytes());
+        for value in [8u32, 8, 40, 104, 120, 124] {
+            data.extend(value.to_le_bytes());
+        }
+        data.extend(pixels);
+        data.extend([0; 16 + 4 + 1]);
+        if let Some(palette) = palette {
+            data.extend(256u16.to_le_bytes());
+            data.extend(palette.0.iter().flatten());
+        }
+        data
+    }
+
+    fn palette(index: usize, color: [u8; 3]) -> Palette {
+        let mut palette = Palette([[0; 3]; 256]);
+        palette.0[index] = color;
+        palette.0[255] = [0, 0, 255];
+        palette
+    }
+
+    #[test]
+    fn wad2_textures_use_the_given_palette() {
+        let bytes = wad(
+            b"WAD2",
+            &[(
+                "Brick",
+                LUMP_TYPE_WAD2_MIPTEX,
+                miptex("Brick", [1; 64], None),
+            )],
+        );
+        let wad = Wad::parse(bytes).unwrap();
+        assert_eq!(wad.version(), WadVersion::Wad2);
+        assert_eq!(wad.texture_names().collect::<Vec<_>>(), ["Brick"]);
+        assert!(wad.decode_texture("stone", None).is_none());
+        assert!(matches!(
+            wad.decode_texture("brick", None),
+            Some(Err(WadError::MissingPalette(_)))
+        ));
+
+        // looked up ignoring case
+        let texture = wad
+            .decode_texture("BRICK", Some(&palette(1, [10, 20, 30])))
+            .unwrap()
+            .unwrap();
+        assert_eq!((texture.width, texture.height), (8, 8));
+        assert_eq!(texture.rgba.len(), 8 * 8 * 4);
+        assert_eq!(texture.rgba[..4], [10, 20, 30, 255]);
+    }
+
+    #[test]
+    fn last_color_of_transparent_textures_is_clear() {
+        let mut pixels = [1; 64];
+        pixels[0] = 255;
+        let bytes = wad(
+            b"WAD2",
+            &[
+                (
+                    "{fence",
+                    LUMP_TYPE_WAD2_MIPTEX,
+                    miptex("{fence", pixels, None),
+                ),
+                (
+                    "grate",
+                    LUMP_TYPE_WAD2_MIPTEX,
+                    miptex("grate", pixels, None),
+                ),
+            ],
+        );
+        let wad = Wad::parse(bytes).unwrap();
+        let palette = palette(1, [10, 20, 30]);
+
+        let fence = wad
+            .decode_texture("{fence", Some(&palette))
+            .unwrap()
+            .unwrap();
+        assert!(fence.is_transparent());
+        assert_eq!(fence.rgba[..8], [0, 0, 0, 0, 10, 20, 30, 255]);
+        // other textures keep that color
+        let grate = wad
+            .decode_texture("grate", Some(&palette))
+            .unwrap()
+            .unwrap();
+        assert_eq!(grate.rgba[..4], [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn wad3_textures_carry_their_palette() {
+        let embedded = palette(1, [1, 2, 3]);
+        let bytes = wad(
+            b"WAD3",
+            &[(
+                "water",
+                LUMP_TYPE_WAD3_MIPTEX,
+                miptex("water", [1; 64], Some(&embedded)),
+            )],
+        );
+        let wad = Wad::parse(bytes).unwrap();
+        assert_eq!(wad.version(), WadVersion::Wad3);
+
+        // the embedded palette wins over the given one
+        let texture = wad
+            .decode_texture("water", Some(&palette(1, [10, 20, 30])))
+            .unwrap()
+            .unwrap();
+        assert_eq!(texture.rgba[..4], [1, 2, 3, 255]);
+    }
+
+    #[test]
+    fn bad_headers_are_truncated() {
+        assert!(matches!(
+            Wad::parse(b"WAD1".to_vec()),
+            Err(WadError::InvalidMagic)
+        ));
+
+        // a directory bigger than the file
+        let mut bytes = wad(b"WAD2", &[]);
+        bytes[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(Wad::parse(bytes), Err(WadError::Truncated(_))));
+
+        // dimensions that overflow
+        let mut data = miptex("huge", [1; 64], None);
+        data[16..24].copy_from_slice(&[0xff; 8]);
+        let wad = Wad::parse(wad(b"WAD2", &[("huge", LUMP_TYPE_WAD2_MIPTEX, data)])).unwrap();
+        assert!(matches!(
+            wad.decode_texture("huge", Some(&palette(1, [10, 20, 30]))),
+            Some(Err(WadError::Truncated(_)))
+        ));
+    }
+}
@@ -0,0 +1,1188 @@
+//! Loads `tests/assets/triggers.map` without rendering and walks an instigator through its triggers.
+//! Runs against the enabled physics backend, both are expected to behave the same:
+//! `cargo test` for rapier, `cargo test --no-default-features --features avian` for avian.
+#![cfg(any(feature = "rapier", feature = "avian"))]
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use qevy::classes::MapClassApp;
+use qevy::components::*;
+use qevy::io::Input;
+use qevy::paths::PathGraph;
+use qevy::targets::{Activated, TargetMessage};
+use std::time::Duration;
+
+const FRAME: Duration = Duration::from_nanos(16_666_667);
+
+/// Where the triggers of `triggers.map` are, in bevy space with Trenchbroom units
+const OUTSIDE: Vec3 = Vec3::new(-100.0, 32.0, 0.0);
+const HALL_FIRST_BRUSH: Vec3 = Vec3::new(0.0, 32.0, 0.0);
+const HALL_SECOND_BRUSH: Vec3 = Vec3::new(0.0, 32.0, 32.0);
+const ONCE: Vec3 = Vec3::new(200.0, 32.0, 0.0);
+const REPEAT: Vec3 = Vec3::new(400.0, 32.0, 0.0);
+const LAMPS: Vec3 = Vec3::new(600.0, 32.0, 0.0);
+const OUTPUTS: Vec3 = Vec3::new(800.0, 32.0, 0.0);
+const COUNTER: Vec3 = Vec3::new(1000.0, 32.0, 0.0);
+/// next to the `pair` doors, in their touch trigger
+const DOORS: Vec3 = Vec3::new(1400.0, 32.0, 60.0);
+/// in the `*slime` brush, 64 units deep
+const SLIME: Vec3 = Vec3::new(2200.0, 16.0, 0.0);
+
+#[derive(Debug, PartialEq)]
+enum Recorded {
+    Entered(String),
+    Exited(String),
+    Fired(String),
+    /// the targetname of the activated entity
+    Activated(String),
+    /// an activation seen by an observer
+    Observed(String),
+    Message(String),
+    /// an input received by an entity, with its targetname or `activator`
+    Input(String, String, Option<String>),
+    LiquidEntered(LiquidKind),
+    LiquidExited(LiquidKind),
+    LiquidDepth(f32),
+}
+
+#[derive(Resource, Default)]
+struct Log(Vec<Recorded>);
+
+impl Log {
+    fn count(&self, recorded: &Recorded) -> usize {
+        self.0.iter().filter(|r| *r == recorded).count()
+    }
+}
+
+#[derive(Component)]
+struct Instigator;
+
+fn target_of(
+    triggers: &Query<(Option<&TriggerOnce>, Option<&TriggerMultiple>)>,
+    entity: Entity,
+) -> String {
+    match triggers.get(entity) {
+        Ok((Some(once), _)) => once.target.clone(),
+        Ok((_, Some(multiple))) => multiple.target.clone(),
+        _ => String::new(),
+    }
+}
+
+/// The events sent by the trigger systems
+#[derive(SystemParam)]
+struct TriggerEvents<'w, 's> {
+    entered: EventReader<'w, 's, TriggerEntered>,
+    exited: EventReader<'w, 's, TriggerExited>,
+    fired: EventReader<'w, 's, TriggeredEvent>,
+}
+
+fn record_system(
+    mut log: ResMut<Log>,
+    mut trigger_events: TriggerEvents,
+    mut activated: EventReader<Activated>,
+    mut messages: EventReader<TargetMessage>,
+    triggers: Query<(Option<&TriggerOnce>, Option<&TriggerMultiple>)>,
+    names: Query<&TriggerTarget>,
+) {
+    for event in trigger_events.exited.read() {
+        log.0
+            .push(Recorded::Exited(target_of(&triggers, event.trigger)));
+    }
+    for event in trigger_events.entered.read() {
+        log.0
+            .push(Recorded::Entered(target_of(&triggers, event.trigger)));
+    }
+    for event in trigger_events.fired.read() {
+        log.0.push(Recorded::Fired(event.target.clone()));
+    }
+    for event in activated.read() {
+        let name = names.get(event.target_entity).unwrap();
+        log.0.push(Recorded::Activated(name.target_name.clone()));
+    }
+    for event in messages.read() {
+        log.0.push(Recorded::Message(event.message.clone()));
+    }
+}
+
+fn record_liquid_system(
+    mut log: ResMut<Log>,
+    mut entered: EventReader<LiquidEntered>,
+    mut exited: EventReader<LiquidExited>,
+    mut depths: EventReader<LiquidDepth>,
+    liquids: Query<&LiquidVolume>,
+) {
+    let kind = |liquid| liquids.get(liquid).unwrap().kind;
+    for event in entered.read() {
+        log.0.push(Recorded::LiquidEntered(kind(event.liquid)));
+    }
+    for event in depths.read() {
+        log.0.push(Recorded::LiquidDepth(event.depth));
+    }
+    for event in exited.read() {
+        log.0.push(Recorded::LiquidExited(kind(event.liquid)));
+    }
+}
+
+fn record_input_observer(
+    trigger: Trigger<Input>,
+    mut log: ResMut<Log>,
+    names: Query<&TriggerTarget>,
+    instigators: Query<(), With<Instigator>>,
+) {
+    let receiver = match names.get(trigger.entity()) {
+        Ok(name) => name.target_name.clone(),
+        Err(_) if instigators.contains(trigger.entity()) => "activator".into(),
+        Err(_) => return,
+    };
+    log.0.push(Recorded::Input(
+        receiver,
+        trigger.input.clone(),
+        trigger.parameter.clone(),
+    ));
+}
+
+fn record_observer(
+    trigger: Trigger<Activated>,
+    mut log: ResMut<Log>,
+    names: Query<&TriggerTarget>,
+) {
+    assert_eq!(trigger.entity(), trigger.target_entity);
+    let name = names.get(trigger.entity()).unwrap();
+    log.0.push(Recorded::Observed(name.target_name.clone()));
+}
+
+#[cfg(feature = "avian")]
+fn add_physics(app: &mut App) {
+    app.add_plugins(avian3d::PhysicsPlugins::default())
+        .add_systems(
+            Update,
+            record_system.after(qevy::gameplay_systems::avian_trigger_system),
+        );
+}
+
+#[cfg(feature = "avian")]
+fn instigator() -> impl Bundle {
+    (
+        avian3d::prelude::RigidBody::Kinematic,
+        avian3d::prelude::Collider::sphere(4.0),
+    )
+}
+
+#[cfg(not(feature = "avian"))]
+fn add_physics(app: &mut App) {
+    app.add_plugins(bevy_rapier3d::prelude::RapierPhysicsPlugin::<
+        bevy_rapier3d::prelude::NoUserData,
+    >::default())
+        .add_systems(
+            Update,
+            record_system.after(qevy::gameplay_systems::rapier_trigger_system),
+        );
+}
+
+#[cfg(not(feature = "avian"))]
+fn instigator() -> impl Bundle {
+    (
+        bevy_rapier3d::prelude::RigidBody::KinematicPositionBased,
+        bevy_rapier3d::prelude::Collider::ball(4.0),
+    )
+}
+
+/// The collision layers of the colliders under a brush entity, `None` for the default ones
+#[cfg(feature = "avian")]
+fn collider_layers(app: &mut App, entity: Entity) -> Vec<Option<u32>> {
+    let world = app.world();
+    world
+        .get::<Children>(entity)
+        .into_iter()
+        .flatten()
+        .filter(|child| world.get::<avian3d::prelude::Collider>(**child).is_some())
+        .map(|child| {
+            world
+                .get::<avian3d::prelude::CollisionLayers>(*child)
+                .map(|layers| layers.memberships.0)
+        })
+        .collect()
+}
+
+/// The collision layers of the colliders under a brush entity, `None` for the default ones
+#[cfg(not(feature = "avian"))]
+fn collider_layers(app: &mut App, entity: Entity) -> Vec<Option<u32>> {
+    let world = app.world();
+    world
+        .get::<Children>(entity)
+        .into_iter()
+        .flatten()
+        .filter(|child| {
+            world
+                .get::<bevy_rapier3d::prelude::Collider>(**child)
+                .is_some()
+        })
+        .map(|child| {
+            world
+                .get::<bevy_rapier3d::prelude::CollisionGroups>(*child)
+                .map(|groups| groups.memberships.bits())
+        })
+        .collect()
+}
+
+/// The shapes of the colliders under a brush entity
+#[cfg(feature = "avian")]
+fn collider_shapes(app: &mut App, entity: Entity) -> Vec<&'static str> {
+    use avian3d::parry::shape::ShapeType;
+    let world = app.world();
+    world
+        .get::<Children>(entity)
+        .into_iter()
+        .flatten()
+        .filter_map(|child| world.get::<avian3d::prelude::Collider>(*child))
+        .map(|collider| match collider.shape().shape_type() {
+            ShapeType::ConvexPolyhedron => "convex",
+            ShapeType::Compound => "compound",
+            ShapeType::TriMesh => "trimesh",
+            _ => "other",
+        })
+        .collect()
+}
+
+/// The shapes of the colliders under a brush entity
+#[cfg(not(feature = "avian"))]
+fn collider_shapes(app: &mut App, entity: Entity) -> Vec<&'static str> {
+    use bevy_rapier3d::parry::shape::ShapeType;
+    let world = app.world();
+    world
+        .get::<Children>(entity)
+        .into_iter()
+        .flatten()
+        .filter_map(|child| world.get::<bevy_rapier3d::prelude::Collider>(*child))
+        .map(|collider| match collider.raw.shape_type() {
+            ShapeType::ConvexPolyhedron => "convex",
+            ShapeType::Compound => "compound",
+            ShapeType::TriMesh => "trimesh",
+            _ => "other",
+        })
+        .collect()
+}
+
+/// Whether a brush entity is moved as a kinematic body
+#[cfg(feature = "avian")]
+fn is_kinematic(app: &mut App, entity: Entity) -> bool {
+    app.world().get::<avian3d::prelude::RigidBody>(entity)
+        == Some(&avian3d::prelude::RigidBody::Kinematic)
+}
+
+/// Whether a brush entity is moved as a kinematic body
+#[cfg(not(feature = "avian"))]
+fn is_kinematic(app: &mut App, entity: Entity) -> bool {
+    let world = app.world();
+    world
+        .get::<Children>(entity)
+        .into_iter()
+        .flatten()
+        .filter_map(|child| world.get::<bevy_rapier3d::prelude::RigidBody>(*child))
+        .any(|body| *body == bevy_rapier3d::prelude::RigidBody::KinematicPositionBased)
+}
+
+/// A headless app with `triggers.map` loaded with the given transform, and the instigator outside of every trigger
+fn app(map_transform: Transform) -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        TransformPlugin,
+        HierarchyPlugin,
+        AssetPlugin {
+            file_path: "tests/assets".into(),
+            ..default()
+        },
+        bevy::scene::ScenePlugin,
+        qevy::MapAssetLoaderPlugin {
+            headless: true,
+            units: MapUnits::Trenchbroom,
+            ..default()
+        },
+    ))
+    .init_asset::<Mesh>()
+    .init_resource::<Log>()
+    .add_systems(Update, record_liquid_system)
+    .add_observer(record_observer)
+    .add_observer(record_input_observer)
+    .insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+    add_physics(&mut app);
+
+    let asset = app.world().resource::<AssetServer>().load("triggers.map");
+    app.world_mut().spawn((Map { asset }, map_transform));
+    app.world_mut().spawn((
+        Instigator,
+        TriggerInstigator,
+        Transform::from_translation(OUTSIDE),
+        instigator(),
+    ));
+
+    for _ in 0..1000 {
+        app.update();
+        let world = app.world_mut();
+        if world.query::<&TriggerOnce>().iter(world).next().is_some() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let world = app.world_mut();
+    assert_eq!(
+        world.query::<&TriggerOnce>().iter(world).count(),
+        2,
+        "map didn't load"
+    );
+    assert_eq!(world.query::<&TriggerMultiple>().iter(world).count(), 4);
+
+    // lets the colliders settle in before anything moves
+    run(&mut app, 5);
+    app.world_mut().resource_mut::<Log>().0.clear();
+    app
+}
+
+fn move_to(app: &mut App, translation: Vec3) {
+    let world = app.world_mut();
+    let mut query = world.query_filtered::<&mut Transform, With<Instigator>>();
+    query.single_mut(world).translation = translation;
+}
+
+fn run(app: &mut App, frames: usize) {
+    for _ in 0..frames {
+        app.update();
+    }
+}
+
+fn hall() -> String {
+    "hall".into()
+}
+
+#[test]
+fn entering_and_leaving_a_trigger() {
+    let mut app = app(Transform::default());
+
+    move_to(&mut app, HALL_FIRST_BRUSH);
+    run(&mut app, 10);
+    {
+        let log = app.world().resource::<Log>();
+        assert_eq!(log.count(&Recorded::Entered(hall())), 1);
+        assert_eq!(log.count(&Recorded::Fired(hall())), 1);
+        assert_eq!(log.count(&Recorded::Exited(hall())), 0);
+    }
+
+    // both brushes are the same trigger, crossing from one to the other isn't leaving it
+    move_to(&mut app, HALL_SECOND_BRUSH);
+    run(&mut app, 10);
+    {
+        let log = app.world().resource::<Log>();
+        assert_eq!(log.count(&Recorded::Entered(hall())), 1);
+        assert_eq!(log.count(&Recorded::Exited(hall())), 0);
+    }
+
+    move_to(&mut app, OUTSIDE);
+    run(&mut app, 10);
+    {
+        let log = app.world().resource::<Log>();
+        assert_eq!(log.count(&Recorded::Exited(hall())), 1);
+        assert_eq!(log.count(&Recorded::Fired(hall())), 1);
+    }
+
+    // without a `wait`, entering again fires again
+    move_to(&mut app, HALL_FIRST_BRUSH);
+    run(&mut app, 10);
+    let log = app.world().resource::<Log>();
+    assert_eq!(log.count(&Recorded::Entered(hall())), 2);
+    assert_eq!(log.count(&Recorded::Fired(hall())), 2);
+
+    let world = app.world_mut();
+    let occupied = world
+        .query::<&TriggerOccupancy>()
+        .iter(world)
+        .filter(|occupancy| !occupancy.occupants.is_empty())
+        .count();
+    assert_eq!(occupied, 1);
+}
+
+#[test]
+fn trigger_once_fires_once() {
+    let mut app = app(Transform::default());
+
+    for _ in 0..2 {
+        move_to(&mut app, ONCE);
+        run(&mut app, 10);
+        move_to(&mut app, OUTSIDE);
+        run(&mut app, 10);
+    }
+
+    let log = app.world().resource::<Log>();
+    assert_eq!(log.count(&Recorded::Entered("once".into())), 2);
+    assert_eq!(log.count(&Recorded::Exited("once".into())), 2);
+    assert_eq!(log.count(&Recorded::Fired("once".into())), 1);
+}
+
+#[test]
+fn wait_fires_again_while_inside() {
+    let mut app = app(Transform::default());
+
+    // 0.5s of wait: fires when entering, then at 0.5s and 1s
+    move_to(&mut app, REPEAT);
+    run(&mut app, 70);
+
+    let log = app.world().resource::<Log>();
+    assert_eq!(log.count(&Recorded::Entered("repeat".into())), 1);
+    assert_eq!(log.count(&Recorded::Fired("repeat".into())), 3);
+}
+
+#[test]
+fn triggers_follow_the_map_transform() {
+    let rotation = Quat::from_rotation_y(std::f32::consts::FRAC_PI_2);
+    let mut app = app(Transform::from_rotation(rotation));
+
+    // where the trigger would be if the map wasn't rotated
+    move_to(&mut app, ONCE);
+    run(&mut app, 10);
+    assert!(app.world().resource::<Log>().0.is_empty());
+
+    move_to(&mut app, rotation * ONCE);
+    run(&mut app, 10);
+    let log = app.world().resource::<Log>();
+    assert_eq!(log.count(&Recorded::Entered("once".into())), 1);
+    assert_eq!(log.count(&Recorded::Fired("once".into())), 1);
+}
+
+#[test]
+fn targets_are_resolved_after_the_delay() {
+    let mut app = app(Transform::default());
+    let lamps = || Recorded::Activated("lamps".into());
+
+    move_to(&mut app, LAMPS);
+    run(&mut app, 5);
+    {
+        let log = app.world().resource::<Log>();
+        assert_eq!(log.count(&Recorded::Fired("lamps".into())), 1);
+        assert_eq!(log.count(&lamps()), 0);
+    }
+
+    // 0.25s of delay, then both entities named `lamps` are activated and the crate is killed
+    run(&mut app, 20);
+    let log = app.world().resource::<Log>();
+    assert_eq!(log.count(&lamps()), 2);
+    assert_eq!(log.count(&Recorded::Observed("lamps".into())), 2);
+    assert_eq!(log.count(&Recorded::Message("The lamps are on".into())), 1);
+
+    let world = app.world_mut();
+    let mut names = world.query::<&TriggerTarget>();
+    let mut names_of = |name: &str| {
+        names
+            .iter(world)
+            .filter(|target| target.target_name == name)
+            .count()
+    };
+    assert_eq!(names_of("lamps"), 2);
+    assert_eq!(names_of("crate"), 0);
+}
+
+/// Loads `triggers.map` a second time, at `transform`
+fn add_second_map(app: &mut App, transform: Transform) {
+    let asset = app.world().resource::<AssetServer>().load("triggers.map");
+    app.world_mut().spawn((Map { asset }, transform));
+    for _ in 0..1000 {
+        app.update();
+        let world = app.world_mut();
+        if world.query::<&TriggerOnce>().iter(world).count() == 4 {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    run(app, 5);
+    app.world_mut().resource_mut::<Log>().0.clear();
+}
+
+#[test]
+fn targets_stay_in_their_map() {
+    let mut app = app(Transform::default());
+    add_second_map(&mut app, Transform::from_xyz(0.0, 0.0, 10000.0));
+
+    // only the lamps and crate of the first map
+    move_to(&mut app, LAMPS);
+    run(&mut app, 25);
+    assert_eq!(
+        app.world()
+            .resource::<Log>()
+            .count(&Recorded::Activated("lamps".into())),
+        2
+    );
+    let world = app.world_mut();
+    let crates = world
+        .query::<&TriggerTarget>()
+        .iter(world)
+        .filter(|target| target.target_name == "crate")
+        .count();
+    assert_eq!(crates, 1);
+}
+
+#[test]
+fn outputs_send_inputs() {
+    let mut app = app(Transform::default());
+    let turn_on = || Recorded::Input("lamps".into(), "TurnOn".into(), Some("bright".into()));
+    let hurt = || Recorded::Input("activator".into(), "Hurt".into(), Some("5".into()));
+
+    // `OnTrigger` has a delay of 0.1s and only fires once, for both lamps
+    for _ in 0..2 {
+        let before = app.world().resource::<Log>().count(&turn_on());
+        move_to(&mut app, OUTPUTS);
+        run(&mut app, 3);
+        assert_eq!(app.world().resource::<Log>().count(&turn_on()), before);
+        run(&mut app, 10);
+        move_to(&mut app, OUTSIDE);
+        run(&mut app, 5);
+    }
+
+    let log = app.world().resource::<Log>();
+    assert_eq!(log.count(&Recorded::Fired(String::new())), 2);
+    assert_eq!(log.count(&turn_on()), 2);
+    assert_eq!(log.count(&hurt()), 2);
+}
+
+#[test]
+fn outputs_stay_in_their_map() {
+    let mut app = app(Transform::default());
+    add_second_map(&mut app, Transform::from_xyz(0.0, 0.0, 10000.0));
+    let turn_on = || Recorded::Input("lamps".into(), "TurnOn".into(), Some("bright".into()));
+
+    // only the lamps of the first map get the input
+    move_to(&mut app, OUTPUTS);
+    run(&mut app, 15);
+    assert_eq!(app.world().resource::<Log>().count(&turn_on()), 2);
+}
+
+#[test]
+fn logic_entities_pass_activations_on() {
+    let mut app = app(Transform::default());
+    let lamps = || Recorded::Activated("lamps".into());
+    let relay = || Recorded::Activated("relay".into());
+
+    // the counter needs two activations, then the manager activates the lamps and the relay 0.2s later
+    move_to(&mut app, COUNTER);
+    run(&mut app, 5);
+    move_to(&mut app, OUTSIDE);
+    run(&mut app, 5);
+    assert_eq!(
+        app.world()
+            .resource::<Log>()
+            .count(&Recorded::Activated("manager".into())),
+        0
+    );
+
+    move_to(&mut app, COUNTER);
+    run(&mut app, 5);
+    {
+        let log = app.world().resource::<Log>();
+        assert_eq!(log.count(&Recorded::Activated("manager".into())), 1);
+        assert_eq!(log.count(&lamps()), 2);
+        assert_eq!(log.count(&relay()), 0);
+    }
+
+    run(&mut app, 20);
+    let log = app.world().resource::<Log>();
+    assert_eq!(log.count(&relay()), 1);
+    assert_eq!(log.count(&lamps()), 4);
+}
+
+#[test]
+fn logic_timer_fires_while_enabled() {
+    let mut app = app(Transform::default());
+    let tick = || Recorded::Activated("tick".into());
+    let toggle = |app: &mut App| {
+        let world = app.world_mut();
+        let instigator = world
+            .query_filtered::<Entity, With<Instigator>>()
+            .single(world);
+        world.send_event(TriggeredEvent {
+            trigger: instigator,
+            target: "ticker".into(),
+            triggered_by: instigator,
+        });
+    };
+
+    // starts disabled
+    run(&mut app, 30);
+    assert_eq!(app.world().resource::<Log>().count(&tick()), 0);
+
+    // fires every 0.25s
+    toggle(&mut app);
+    run(&mut app, 65);
+    assert_eq!(app.world().resource::<Log>().count(&tick()), 4);
+
+    toggle(&mut app);
+    run(&mut app, 60);
+    assert_eq!(app.world().resource::<Log>().count(&tick()), 4);
+}
+
+fn translation_of(app: &mut App, entity: Entity) -> Vec3 {
+    app.world().get::<Transform>(entity).unwrap().translation
+}
+
+fn door_state(app: &mut App, entity: Entity) -> DoorState {
+    app.world().get::<DoorState>(entity).unwrap().clone()
+}
+
+#[test]
+fn door_opens_and_closes() {
+    let mut app = app(Transform::default());
+    let world = app.world_mut();
+    let (gate, instigator) = (
+        world
+            .query_filtered::<Entity, With<FuncDoor>>()
+            .iter(world)
+            .find(|entity| world.get::<TriggerTarget>(*entity).is_some())
+            .unwrap(),
+        world
+            .query_filtered::<Entity, With<Instigator>>()
+            .single(world),
+    );
+    world.send_event(TriggeredEvent {
+        trigger: instigator,
+        target: "gate".into(),
+        triggered_by: instigator,
+    });
+
+    // goes up by its height minus the lip, 64 - 8, at 100 units per second
+    run(&mut app, 5);
+    assert_eq!(door_state(&mut app, gate), DoorState::Opening);
+    assert!(translation_of(&mut app, gate).y > 0.0);
+    run(&mut app, 40);
+    assert!(matches!(
+        door_state(&mut app, gate),
+        DoorState::Open(Some(_))
+    ));
+    assert_eq!(translation_of(&mut app, gate), Vec3::new(0.0, 56.0, 0.0));
+
+    // waits a second, then closes
+    run(&mut app, 100);
+    assert_eq!(door_state(&mut app, gate), DoorState::Closed);
+    assert_eq!(translation_of(&mut app, gate), Vec3::ZERO);
+}
+
+#[test]
+fn door_team_opens_when_touched() {
+    let mut app = app(Transform::default());
+    let world = app.world_mut();
+    let pair = world
+        .query_filtered::<Entity, With<FuncDoor>>()
+        .iter(world)
+        .filter(|entity| world.get::<TriggerTarget>(*entity).is_none())
+        .collect::<Vec<_>>();
+    assert_eq!(pair.len(), 2);
+
+    move_to(&mut app, DOORS);
+    run(&mut app, 30);
+    move_to(&mut app, OUTSIDE);
+    run(&mut app, 60);
+
+    // slide 16 - 8 units apart, and stay open
+    let mut offsets = pair
+        .iter()
+        .map(|door| {
+            assert_eq!(door_state(&mut app, *door), DoorState::Open(None));
+            translation_of(&mut app, *door).x.round()
+        })
+        .collect::<Vec<_>>();
+    offsets.sort_by(f32::total_cmp);
+    assert_eq!(offsets, [-8.0, 8.0]);
+}
+
+fn train_state(app: &mut App, entity: Entity) -> TrainState {
+    app.world().get::<TrainState>(entity).unwrap().clone()
+}
+
+#[test]
+fn train_follows_its_path() {
+    let mut app = app(Transform::default());
+    let world = app.world_mut();
+    let (train, instigator) = (
+        world
+            .query_filtered::<Entity, With<FuncTrain>>()
+            .single(world),
+        world
+            .query_filtered::<Entity, With<Instigator>>()
+            .single(world),
+    );
+    let paths = world.resource::<PathGraph>();
+    let first = paths
+        .iter()
+        .find(|(_, corner)| corner.name == "c1")
+        .unwrap()
+        .0;
+    let path = paths
+        .path(first)
+        .map(|corner| paths.get(corner).unwrap().name.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(path, ["c1", "c2", "c3"]);
+
+    // named trains wait on their first corner, with their lowest corner on it
+    run(&mut app, 30);
+    assert!(matches!(
+        train_state(&mut app, train),
+        TrainState::Stopped(_)
+    ));
+    assert_eq!(translation_of(&mut app, train), Vec3::new(16.0, 0.0, 16.0));
+    app.world_mut().send_event(TriggeredEvent {
+        trigger: instigator,
+        target: "train".into(),
+        triggered_by: instigator,
+    });
+
+    // 100 units to c2 at 100 units per second, where it fires the station and waits
+    run(&mut app, 65);
+    assert!(matches!(
+        train_state(&mut app, train),
+        TrainState::Waiting(_, _)
+    ));
+    assert_eq!(translation_of(&mut app, train), Vec3::new(116.0, 0.0, 16.0));
+    let station = Recorded::Activated("station".into());
+    assert_eq!(app.world().resource::<Log>().count(&station), 1);
+
+    // then up to c3, which stops it
+    run(&mut app, 100);
+    assert!(matches!(
+        train_state(&mut app, train),
+        TrainState::Stopped(_)
+    ));
+    assert_eq!(
+        translation_of(&mut app, train),
+        Vec3::new(116.0, 100.0, 16.0)
+    );
+    assert_eq!(app.world().resource::<Log>().count(&station), 1);
+}
+
+fn named(app: &mut App, name: &str) -> Entity {
+    let world = app.world_mut();
+    world
+        .query::<(Entity, &TriggerTarget)>()
+        .iter(world)
+        .find(|(_, target)| target.target_name == name)
+        .unwrap()
+        .0
+}
+
+fn world_point(app: &mut App, entity: Entity, point: Vec3) -> Vec3 {
+    let transform = app.world().get::<Transform>(entity).unwrap();
+    (transform.transform_point(point) * 100.0).round() / 100.0
+}
+
+#[test]
+fn rotating_turns_about_its_origin() {
+    let mut app = app(Transform::default());
+    let fan = named(&mut app, "fan");
+    // placed on its `origin` key, its blade is relative to it
+    let (pivot, blade) = (Vec3::new(1800.0, 32.0, 0.0), Vec3::new(0.0, 0.0, 32.0));
+    assert_eq!(
+        app.world().get::<BrushPivot>(fan).map(|pivot| pivot.0),
+        Some(Vec3::ZERO)
+    );
+
+    // it starts on, a quarter turn about the up axis each second
+    let start = world_point(&mut app, fan, blade);
+    run(&mut app, 60);
+    assert_eq!(world_point(&mut app, fan, Vec3::ZERO), pivot);
+    let end = world_point(&mut app, fan, blade);
+    let expected = pivot + Quat::from_rotation_y(std::f32::consts::FRAC_PI_2) * (start - pivot);
+    assert!(end.distance(expected) < 0.1, "{end} {expected}");
+
+    // activating it stops it
+    let instigator = app
+        .world_mut()
+        .query_filtered::<Entity, With<Instigator>>()
+        .single(app.world());
+    app.world_mut().send_event(TriggeredEvent {
+        trigger: instigator,
+        target: "fan".into(),
+        triggered_by: instigator,
+    });
+    run(&mut app, 2);
+    let stopped = world_point(&mut app, fan, blade);
+    run(&mut app, 30);
+    assert_eq!(world_point(&mut app, fan, blade), stopped);
+}
+
+#[test]
+fn rotating_door_swings_on_its_origin_brush() {
+    let mut app = app(Transform::default());
+    let hinge = named(&mut app, "hinge");
+    let instigator = app
+        .world_mut()
+        .query_filtered::<Entity, With<Instigator>>()
+        .single(app.world());
+    // placed on the center of its origin brush, which isn't built
+    let (pivot, edge) = (Vec3::new(1900.0, 32.0, 0.0), Vec3::new(32.0, 0.0, 0.0));
+    assert_eq!(translation_of(&mut app, hinge), pivot);
+    let bounds = *app.world().get::<BrushBounds>(hinge).unwrap();
+    assert_eq!((bounds.min.x, bounds.max.x), (0.0, 32.0));
+    app.world_mut().send_event(TriggeredEvent {
+        trigger: instigator,
+        target: "hinge".into(),
+        triggered_by: instigator,
+    });
+
+    // 90 degrees at 180 degrees per second
+    run(&mut app, 40);
+    assert!(matches!(
+        door_state(&mut app, hinge),
+        DoorState::Open(Some(_))
+    ));
+    assert_eq!(world_point(&mut app, hinge, Vec3::ZERO), pivot);
+    assert_eq!(
+        world_point(&mut app, hinge, edge),
+        Vec3::new(1900.0, 32.0, -32.0)
+    );
+
+    // waits a second, then swings back
+    run(&mut app, 100);
+    assert_eq!(door_state(&mut app, hinge), DoorState::Closed);
+    assert_eq!(
+        world_point(&mut app, hinge, edge),
+        Vec3::new(1932.0, 32.0, 0.0)
+    );
+}
+
+/// Runs for at least `seconds` with frames of uneven length, returning how long it ran
+fn run_uneven(app: &mut App, seconds: f32) -> f32 {
+    const FRAMES: [u64; 5] = [5, 31, 12, 40, 9];
+    let mut elapsed = Duration::ZERO;
+    for millis in FRAMES.iter().cycle() {
+        if elapsed.as_secs_f32() >= seconds {
+            break;
+        }
+        let frame = Duration::from_millis(*millis);
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(frame));
+        app.update();
+        elapsed += frame;
+    }
+    app.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+    elapsed.as_secs_f32()
+}
+
+/// With avian, doors move on the fixed physics step while frames come at any rate
+#[test]
+fn doors_get_to_their_ends_at_any_frame_rate() {
+    let mut app = app(Transform::default());
+    let hinge = named(&mut app, "hinge");
+    let world = app.world_mut();
+    let gate = world
+        .query_filtered::<Entity, With<FuncDoor>>()
+        .iter(world)
+        .find(|entity| {
+            world
+                .get::<TriggerTarget>(*entity)
+                .is_some_and(|t| t.target_name == "gate")
+        })
+        .unwrap();
+    let instigator = world
+        .query_filtered::<Entity, With<Instigator>>()
+        .single(world);
+
+    for target in ["gate", "hinge"] {
+        app.world_mut().send_event(TriggeredEvent {
+            trigger: instigator,
+            target: target.into(),
+            triggered_by: instigator,
+        });
+    }
+    run_uneven(&mut app, 0.8);
+    assert!(matches!(door_state(&mut app, gate), DoorState::Open(_)));
+    assert_eq!(
+        world_point(&mut app, gate, Vec3::ZERO),
+        Vec3::new(0.0, 56.0, 0.0)
+    );
+    assert!(matches!(door_state(&mut app, hinge), DoorState::Open(_)));
+    assert_eq!(
+        world_point(&mut app, hinge, Vec3::new(32.0, 0.0, 0.0)),
+        Vec3::new(1900.0, 32.0, -32.0)
+    );
+    let rotation = *app.world().get::<DoorRotation>(hinge).unwrap();
+    assert!((rotation.angle - rotation.open).abs() < 1e-4);
+
+    // they wait a second and close
+    run_uneven(&mut app, 2.2);
+    assert_eq!(door_state(&mut app, gate), DoorState::Closed);
+    assert_eq!(world_point(&mut app, gate, Vec3::ZERO), Vec3::ZERO);
+    assert_eq!(door_state(&mut app, hinge), DoorState::Closed);
+    assert_eq!(
+        world_point(&mut app, hinge, Vec3::new(32.0, 0.0, 0.0)),
+        Vec3::new(1932.0, 32.0, 0.0)
+    );
+}
+
+#[test]
+fn train_gets_to_its_corners_at_any_frame_rate() {
+    let mut app = app(Transform::default());
+    let train = named(&mut app, "train");
+    let instigator = app
+        .world_mut()
+        .query_filtered::<Entity, With<Instigator>>()
+        .single(app.world());
+    app.world_mut().send_event(TriggeredEvent {
+        trigger: instigator,
+        target: "train".into(),
+        triggered_by: instigator,
+    });
+
+    // a second to c2, where it waits half a second
+    run_uneven(&mut app, 1.2);
+    assert!(matches!(
+        train_state(&mut app, train),
+        TrainState::Waiting(_, _)
+    ));
+    assert_eq!(
+        world_point(&mut app, train, Vec3::ZERO),
+        Vec3::new(116.0, 0.0, 16.0)
+    );
+
+    // then a second up to c3
+    run_uneven(&mut app, 1.6);
+    assert!(matches!(
+        train_state(&mut app, train),
+        TrainState::Stopped(_)
+    ));
+    assert_eq!(
+        world_point(&mut app, train, Vec3::ZERO),
+        Vec3::new(116.0, 100.0, 16.0)
+    );
+}
+
+#[test]
+fn rotating_keeps_its_speed_at_any_frame_rate() {
+    let mut app = app(Transform::default());
+    let fan = named(&mut app, "fan");
+    let (pivot, blade) = (Vec3::new(1800.0, 32.0, 0.0), Vec3::new(0.0, 0.0, 32.0));
+
+    // a quarter turn each second, give or take the last physics step
+    let start = world_point(&mut app, fan, blade) - pivot;
+    let elapsed = run_uneven(&mut app, 0.5);
+    let end = world_point(&mut app, fan, blade) - pivot;
+    let turned = start.angle_between(end).to_degrees();
+    assert!(
+        (turned - 90.0 * elapsed).abs() < 2.0,
+        "{turned} in {elapsed}s"
+    );
+}
+
+#[test]
+fn mover_stays_on_its_origin() {
+    let mut app = app(Transform::default());
+    let lift = named(&mut app, "lift");
+    // its handler keeps the transform of its pivot, the brush being relative to it
+    assert_eq!(translation_of(&mut app, lift), Vec3::new(3200.0, 0.0, 0.0));
+    let bounds = *app.world().get::<BrushBounds>(lift).unwrap();
+    assert_eq!((bounds.min.y, bounds.max.y), (0.0, 64.0));
+}
+
+#[test]
+fn texture_rules_decide_colliders() {
+    let mut app = app(Transform::default());
+    let hint = named(&mut app, "hintwall");
+    let clip = named(&mut app, "clipwall");
+
+    // hint brushes aren't solid, player clips are on their own layer
+    assert_eq!(collider_layers(&mut app, hint), []);
+    assert_eq!(
+        collider_layers(&mut app, clip),
+        [Some(qevy::textures::PLAYER_CLIP_LAYER)]
+    );
+}
+
+#[test]
+fn liquids_tell_how_deep_instigators_are() {
+    let mut app = app(Transform::default());
+
+    move_to(&mut app, SLIME);
+    run(&mut app, 10);
+    move_to(&mut app, SLIME + Vec3::Y * 24.0);
+    run(&mut app, 10);
+    move_to(&mut app, OUTSIDE);
+    run(&mut app, 10);
+    // liquids are triggers too, sending `TriggerEntered` and `TriggerExited`
+    let liquid_events = app
+        .world()
+        .resource::<Log>()
+        .0
+        .iter()
+        .filter(|recorded| {
+            matches!(
+                recorded,
+                Recorded::LiquidEntered(_) | Recorded::LiquidDepth(_) | Recorded::LiquidExited(_)
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        liquid_events,
+        [
+            &Recorded::LiquidEntered(LiquidKind::Slime),
+            &Recorded::LiquidDepth(48.0),
+            &Recorded::LiquidDepth(24.0),
+            &Recorded::LiquidExited(LiquidKind::Slime),
+        ]
+    );
+
+    // `func_water` reads its own liquid
+    let pool = named(&mut app, "pool");
+    let pool = app.world().get::<LiquidVolume>(pool).unwrap();
+    assert_eq!(pool.kind, LiquidKind::Lava);
+    assert_eq!(pool.density, LiquidKind::Lava.density());
+    assert_eq!(pool.current, Vec3::new(32.0, 0.0, 0.0));
+}
+
+#[test]
+fn worldspawn_sets_up_the_environment() {
+    let mut app = app(Transform::default());
+    let camera = app.world_mut().spawn(Camera3d::default()).id();
+    run(&mut app, 2);
+
+    let ambient = app.world().resource::<AmbientLight>();
+    assert_eq!(ambient.brightness, 120.0);
+    assert_eq!(ambient.color, Color::srgb(1.0, 128.0 / 255.0, 0.0));
+
+    // the sun shines along `_sun_mangle`, its yaw turning it towards the map's +Y
+    let world = app.world_mut();
+    let (sun, transform) = world
+        .query_filtered::<(&DirectionalLight, &Transform), With<qevy::environment::MapSun>>()
+        .single(world);
+    assert_eq!(sun.illuminance, 5000.0);
+    assert!(transform
+        .forward()
+        .abs_diff_eq(Vec3::new(1.0, -1.0, 0.0).normalize(), 1e-4));
+
+    let fog = app
+        .world()
+        .get::<bevy::pbr::DistanceFog>(camera)
+        .expect("cameras get the worldspawn's fog");
+    assert_eq!(fog.color, Color::srgb(0.2, 0.3, 0.4));
+    assert!(matches!(
+        fog.falloff,
+        bevy::pbr::FogFalloff::ExponentialSquared { density } if density == 0.5 / 64.0
+    ));
+
+    // sky brushes aren't drawn but are still solid
+    let sky = named(&mut app, "skywall");
+    assert_eq!(collider_layers(&mut app, sky), [None]);
+    let world = app.world();
+    assert!(world
+        .get::<Children>(sky)
+        .unwrap()
+        .iter()
+        .any(|child| world.get::<SkyPortal>(*child).is_some()));
+}
+
+#[test]
+fn environment_is_undone_when_removed() {
+    let mut app = app(Transform::default());
+    run(&mut app, 2);
+    assert_eq!(app.world().resource::<AmbientLight>().brightness, 120.0);
+
+    let world = app.world_mut();
+    let (worldspawn, environment) = world
+        .query::<(Entity, &qevy::environment::MapEnvironment)>()
+        .single(world);
+    let environment = environment.clone();
+    let suns = |app: &mut App| {
+        let world = app.world_mut();
+        world
+            .query_filtered::<&Parent, With<qevy::environment::MapSun>>()
+            .iter(world)
+            .map(|parent| parent.get())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(suns(&mut app), [worldspawn]);
+
+    // setting it up again replaces the sun
+    app.world_mut()
+        .entity_mut(worldspawn)
+        .insert(environment.clone());
+    run(&mut app, 1);
+    assert_eq!(suns(&mut app), [worldspawn]);
+
+    // there was no ambient light before the map
+    let remove = |app: &mut App| {
+        app.world_mut()
+            .entity_mut(worldspawn)
+            .remove::<qevy::environment::MapEnvironment>();
+        run(app, 1);
+    };
+    remove(&mut app);
+    assert!(suns(&mut app).is_empty());
+    assert!(app.world().get_resource::<AmbientLight>().is_none());
+
+    app.insert_resource(AmbientLight {
+        brightness: 7.0,
+        ..default()
+    });
+    app.world_mut().entity_mut(worldspawn).insert(environment);
+    run(&mut app, 1);
+    assert_eq!(suns(&mut app), [worldspawn]);
+    assert_eq!(app.world().resource::<AmbientLight>().brightness, 120.0);
+    remove(&mut app);
+    assert_eq!(app.world().resource::<AmbientLight>().brightness, 7.0);
+}
+
+#[test]
+fn entities_pick_their_collider_shape() {
+    let mut app = app(Transform::default());
+    let world = app.world_mut();
+    let worldspawn = world
+        .query::<(Entity, &MapEntityProperties)>()
+        .iter(world)
+        .find(|(_, props)| props.classname == "worldspawn")
+        .unwrap()
+        .0;
+
+    // brushes are convex hulls of their own unless their entity's `_collider` says otherwise
+    assert_eq!(collider_shapes(&mut app, worldspawn), ["compound"]);
+    let gate = named(&mut app, "gate");
+    assert_eq!(collider_shapes(&mut app, gate), ["convex"]);
+    let mesh = named(&mut app, "meshwall");
+    assert_eq!(collider_shapes(&mut app, mesh), ["trimesh"]);
+    let ghost = named(&mut app, "ghostwall");
+    assert!(collider_shapes(&mut app, ghost).is_empty());
+}
+
+#[test]
+fn registered_classes_can_be_kinematic() {
+    let mut app = app(Transform::default());
+    let lift = named(&mut app, "lift");
+    assert!(is_kinematic(&mut app, lift));
+    let plat = named(&mut app, "plat");
+    assert!(!is_kinematic(&mut app, plat));
+
+    // a game class moved by the game, once the map is rebuilt
+    app.register_kinematic_map_class("func_plat", |_, _, _, _| {});
+    let world = app.world_mut();
+    let map = world.query_filtered::<Entity, With<Map>>().single(world);
+    world.get_mut::<Map>(map).unwrap().set_changed();
+    run(&mut app, 2);
+    let plat = named(&mut app, "plat");
+    assert!(is_kinematic(&mut app, plat));
+}
+
+#[test]
+fn classes_registered_before_the_plugin_are_kept() {
+    let mut app = App::new();
+    app.register_map_class("func_door", |_, _, _, _| {})
+        .disable_map_class("func_water")
+        .add_plugins((
+            MinimalPlugins,
+            AssetPlugin::default(),
+            bevy::scene::ScenePlugin,
+            qevy::MapAssetLoaderPlugin {
+                headless: true,
+                ..default()
+            },
+        ));
+
+    let classes = app.world().resource::<qevy::classes::MapClassRegistry>();
+    // the app's door handler isn't replaced by the builtin kinematic one
+    assert!(classes.get("func_door").is_some());
+    assert!(!classes.is_kinematic("func_door"));
+    assert!(classes.get("func_water").is_none());
+    assert!(classes.get("trigger_once").is_some());
+    assert!(classes.is_kinematic("func_train"));
+}